@@ -0,0 +1,219 @@
+use crate::utils;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the activity log in the application's data directory.
+pub const LOG_FILE: &str = "activity.jsonl";
+/// The log is read backwards in blocks of this many bytes to find its last events.
+const BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Something that happened in the application, such as a rename or a failure to read a folder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    /// When it happened, in RFC 3339.
+    pub time: String,
+    /// What happened, e.g. `Rename`, with `Failed` appended if it failed, e.g. `RenameFailed`.
+    pub kind: String,
+    /// The document or folder it happened to, as `utils::path_to_string` gives it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Where the document ended up, for renames and moves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_path: Option<String>,
+    /// The error for failures, or anything else worth knowing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Event {
+    pub fn new(kind: &str) -> Event {
+        Event {
+            time: Utc::now().to_rfc3339(),
+            kind: kind.to_string(),
+            path: None,
+            new_path: None,
+            detail: None,
+        }
+    }
+
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Event {
+        self.path = Some(utils::path_to_string(path));
+        self
+    }
+
+    pub fn new_path<P: AsRef<Path>>(mut self, path: P) -> Event {
+        self.new_path = Some(utils::path_to_string(path));
+        self
+    }
+
+    pub fn detail<D: fmt::Display>(mut self, detail: D) -> Event {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    pub fn is_failure(&self) -> bool {
+        self.kind.ends_with("Failed")
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(path) = &self.path {
+            write!(f, " {}", utils::path_from_string(path).display())?;
+        }
+        if let Some(new_path) = &self.new_path {
+            write!(f, " -> {}", utils::path_from_string(new_path).display())?;
+        }
+        if let Some(detail) = &self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// A log of events with one JSON object per line, which is only ever appended to.
+#[derive(Debug, Clone)]
+pub struct ActivityLog {
+    path: PathBuf,
+}
+
+impl ActivityLog {
+    pub fn new<P: Into<PathBuf>>(path: P) -> ActivityLog {
+        ActivityLog { path: path.into() }
+    }
+
+    pub fn append(&self, event: &Event) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+
+    fn open(&self) -> io::Result<Option<File>> {
+        match File::open(&self.path) {
+            Ok(file) => Ok(Some(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The last `limit` events, oldest first, and the offset in the log they were read up to,
+    /// to read the events appended later with `since`. Only the end of the log is read. Lines
+    /// that can't be read, such as one cut short by a crash, are left out.
+    pub fn recent(&self, limit: usize) -> io::Result<(Vec<Event>, u64)> {
+        let mut file = match self.open()? {
+            Some(file) => file,
+            None => return Ok((Vec::new(), 0)),
+        };
+        let mut start = file.metadata()?.len();
+        let mut data = Vec::new();
+        // Unless the whole log is read, the first line may only be the end of one, so one more
+        // line than needed is read and the first one is dropped.
+        while start > 0 && data.iter().filter(|&&b| b == b'\n').count() <= limit {
+            let size = BLOCK_SIZE.min(start);
+            start -= size;
+            let mut block = vec![0; size as usize];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut block)?;
+            block.append(&mut data);
+            data = block;
+        }
+        let first = match start {
+            0 => 0,
+            _ => data.iter().position(|&b| b == b'\n').map_or(0, |i| i + 1),
+        };
+        let (mut events, end) = parse(&data[first..]);
+        let skipped = events.len().saturating_sub(limit);
+        events.drain(..skipped);
+        Ok((events, start + (first + end) as u64))
+    }
+
+    /// The events appended after `offset`, as given by `recent` or an earlier call, and the
+    /// offset they were read up to. If the log was replaced by a shorter one, it's read from
+    /// the start.
+    pub fn since(&self, offset: u64) -> io::Result<(Vec<Event>, u64)> {
+        let mut file = match self.open()? {
+            Some(file) => file,
+            None => return Ok((Vec::new(), 0)),
+        };
+        let len = file.metadata()?.len();
+        let offset = if len < offset { 0 } else { offset };
+        if len == offset {
+            return Ok((Vec::new(), offset));
+        }
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_to_end(&mut data)?;
+        let (events, end) = parse(&data);
+        Ok((events, offset + end as u64))
+    }
+}
+
+/// The events of the complete lines of `data`, and the number of bytes they take. A last line
+/// without a newline is being written, or was cut short.
+fn parse(data: &[u8]) -> (Vec<Event>, usize) {
+    let end = data.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let events = data[..end]
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect();
+    (events, end)
+}
+
+#[test]
+fn test_activity_log() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let log = ActivityLog::new(dir.join(LOG_FILE));
+    assert!(log.recent(10).unwrap().0.is_empty());
+    let rename = Event::new("Rename")
+        .path("scan.pdf")
+        .new_path("2020-01-01_Bank_Statement_1.pdf");
+    assert_eq!(
+        rename.to_string(),
+        "Rename scan.pdf -> 2020-01-01_Bank_Statement_1.pdf"
+    );
+    log.append(&rename).unwrap();
+    fs::OpenOptions::new()
+        .append(true)
+        .open(dir.join(LOG_FILE))
+        .unwrap()
+        .write_all(b"{\"time\":\n")
+        .unwrap();
+    log.append(
+        &Event::new("OpenFailed")
+            .path("/nowhere")
+            .detail("not found"),
+    )
+    .unwrap();
+    let (events, offset) = log.recent(10).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0], rename);
+    assert!(events[1].is_failure());
+    assert_eq!(log.recent(1).unwrap().0[0].kind, "OpenFailed");
+    assert_eq!(log.since(offset).unwrap(), (Vec::new(), offset));
+
+    // Only the end of a long log is read, from the middle of a line.
+    for i in 0..2000 {
+        log.append(&Event::new("Preview").path(format!("{}.pdf", i)))
+            .unwrap();
+    }
+    let (events, end) = log.recent(3).unwrap();
+    let paths: Vec<_> = events.iter().map(|e| e.path.clone().unwrap()).collect();
+    assert_eq!(paths, ["1997.pdf", "1998.pdf", "1999.pdf"]);
+    let (appended, appended_end) = log.since(offset).unwrap();
+    assert_eq!(appended.len(), 2000);
+    assert_eq!(appended[1999], events[2]);
+    assert_eq!(appended_end, end);
+}
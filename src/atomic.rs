@@ -0,0 +1,181 @@
+//! Crash-safe replacement of file contents.
+//!
+//! New contents are written to a temporary file next to their destination, read back and
+//! compared by checksum, and only then moved into place. An interrupted write leaves at most a
+//! temporary file behind, which `remove_temp_files` cleans up when the cabinet is next opened.
+use data_encoding::HEXLOWER;
+use ring::digest::{digest, SHA256};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension of temporary files, e.g. `.2020-01-01_Bank_Statement_1.pdf.filecabinet-tmp`.
+pub const TEMP_EXTENSION: &str = "filecabinet-tmp";
+
+/// Hex-encoded SHA-256 of `data`.
+pub fn checksum(data: &[u8]) -> String {
+    HEXLOWER.encode(digest(&SHA256, data).as_ref())
+}
+
+/// The hidden temporary file that contents destined for `target` are written to.
+pub fn temp_path<P: AsRef<Path>>(target: P) -> PathBuf {
+    let target = target.as_ref();
+    let mut name = std::ffi::OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(".");
+    name.push(TEMP_EXTENSION);
+    target.with_file_name(name)
+}
+
+pub fn is_temp<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|ext| ext == TEMP_EXTENSION)
+}
+
+/// Writes `contents` to `target` through a verified temporary file. Fails without touching
+/// `target` if it already exists or the written file doesn't match `contents`.
+pub fn write<P: AsRef<Path>>(target: P, contents: &[u8]) -> io::Result<()> {
+    commit(target.as_ref(), contents, atomicwrites::move_atomic)
+}
+
+/// Like `write`, but replaces `target` if it exists.
+pub fn replace<P: AsRef<Path>>(target: P, contents: &[u8]) -> io::Result<()> {
+    commit(target.as_ref(), contents, atomicwrites::replace_atomic)
+}
+
+/// Moves the file at `source` to `target`, failing if `target` exists. Moves between
+/// filesystems go through a verified copy, and `source` is only removed once it's in place.
+pub fn move_file<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) -> io::Result<()> {
+    let (source, target) = (source.as_ref(), target.as_ref());
+    match atomicwrites::move_atomic(source, target) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists && source.is_file() => {
+            write(target, &fs::read(source)?)?;
+            fs::remove_file(source)
+        }
+        result => result,
+    }
+}
+
+fn commit(
+    target: &Path,
+    contents: &[u8],
+    rename: fn(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let temp = temp_path(target);
+    let result = write_temp(&temp, contents).and_then(|_| rename(&temp, target));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_temp(temp: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    file.seek(SeekFrom::Start(0))?;
+    let mut written = Vec::with_capacity(contents.len());
+    file.read_to_end(&mut written)?;
+    if checksum(&written) != checksum(contents) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("checksum mismatch in \"{}\"", temp.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// An advisory lock on a file, released when it's dropped or when the process ends, even if
+/// it's killed. Any number of shared locks can be held at once, while an exclusive one keeps
+/// out every other lock, including the ones of the same process.
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Takes an exclusive lock on the file at `path`, creating it if needed. Fails with
+    /// `WouldBlock` if another lock is held on it.
+    pub fn exclusive<P: AsRef<Path>>(path: P) -> io::Result<Lock> {
+        Self::take(path.as_ref(), false)
+    }
+
+    /// Takes a shared lock on the file at `path`, creating it if needed. Fails with
+    /// `WouldBlock` if an exclusive lock is held on it.
+    pub fn shared<P: AsRef<Path>>(path: P) -> io::Result<Lock> {
+        Self::take(path.as_ref(), true)
+    }
+
+    fn take(path: &Path, shared: bool) -> io::Result<Lock> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let result = if shared {
+            file.try_lock_shared()
+        } else {
+            file.try_lock()
+        };
+        match result {
+            Ok(()) => Ok(Lock { _file: file }),
+            Err(TryLockError::WouldBlock) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+}
+
+/// Removes temporary files left in `dir` by interrupted writes and returns their paths.
+pub fn remove_temp_files<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for entry in dir.as_ref().read_dir()? {
+        let path = entry?.path();
+        if is_temp(&path) && path.is_file() {
+            fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+#[test]
+fn test_write() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let target = dir.join("2020-01-01_Bank_Statement_1.pdf");
+    write(&target, b"statement").unwrap();
+    assert_eq!(fs::read(&target).unwrap(), b"statement");
+    assert!(write(&target, b"other").is_err());
+    assert_eq!(fs::read(&target).unwrap(), b"statement");
+
+    let moved = dir.join("2020-01-01_Bank_Statement_2.pdf");
+    move_file(&target, &moved).unwrap();
+    assert!(!target.exists());
+    fs::write(&target, b"other").unwrap();
+    assert!(move_file(&target, &moved).is_err());
+    assert_eq!(fs::read(&moved).unwrap(), b"statement");
+
+    fs::write(temp_path(&target), b"interrupted").unwrap();
+    assert_eq!(remove_temp_files(dir).unwrap(), vec![temp_path(&target)]);
+}
+
+#[test]
+fn test_lock() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("lock");
+    let shared = Lock::shared(&path).unwrap();
+    let other = Lock::shared(&path).unwrap();
+    assert!(Lock::exclusive(&path).is_err());
+    drop((shared, other));
+    let exclusive = Lock::exclusive(&path).unwrap();
+    assert!(Lock::shared(&path).is_err());
+    drop(exclusive);
+    assert!(Lock::exclusive(&path).is_ok());
+}
@@ -0,0 +1,1011 @@
+use crate::atomic;
+use crate::config::{Config, CONFIG_DIR};
+use crate::conflict::{self, ConflictPolicy};
+use crate::crypto::{self, Key};
+use crate::errors::{Error, ErrorKind, Result};
+use crate::journal::{Action, FileState, Journal, Operation};
+use crate::manifest::{Manifest, Report};
+use crate::template::Template;
+use crate::trash::{Entry as TrashEntry, Trash, TRASH_DIR};
+use crate::{duplicates, shred, utils, Document};
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const KEYFILE: &str = "keyfile";
+/// Holds the new key while a rotation is in progress, so an interrupted one can be resumed.
+const NEW_KEYFILE: &str = "keyfile.new";
+/// Locked exclusively while temporary files are cleaned up or the key is rotated, and shared
+/// while documents are written.
+const LOCK_FILE: &str = "lock";
+
+/// A directory of documents together with its configuration.
+#[derive(Debug, Clone)]
+pub struct Cabinet {
+    root: PathBuf,
+    config: Config,
+}
+
+impl Cabinet {
+    /// Opens the cabinet at `root`, loading its config.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Cabinet> {
+        let root = root.as_ref().to_path_buf();
+        let config = Config::load(&root)?;
+        Ok(Cabinet { root, config })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn template(&self) -> &Template {
+        &self.config.template
+    }
+
+    /// Changes the cabinet's config and saves it.
+    pub fn set_config(&mut self, config: Config) -> Result<()> {
+        config.save(&self.root)?;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Removes the temporary files that interrupted writes left in the cabinet's folders, its
+    /// inbox and its config, and returns their paths. Meant to run when the cabinet is opened.
+    /// Nothing is removed while another process holds the cabinet's lock, as its writes may
+    /// still be in progress, or if the cabinet has no config folder to hold the lock.
+    pub fn remove_temp_files(&self) -> Result<Vec<PathBuf>> {
+        if !self.root.join(CONFIG_DIR).is_dir() {
+            return Ok(Vec::new());
+        }
+        let _lock = match self.lock() {
+            Err(Error(ErrorKind::Locked, _)) => return Ok(Vec::new()),
+            lock => lock?,
+        };
+        let mut dirs = vec![self.root.join(CONFIG_DIR), self.root.clone()];
+        if self.config.scans_subfolders() {
+            dirs.extend(utils::list_folders(&self.root)?);
+        }
+        dirs.extend(
+            self.inbox()
+                .filter(|inbox| inbox.is_dir() && !dirs.contains(inbox)),
+        );
+        let mut removed = Vec::new();
+        for dir in dirs {
+            removed.extend(atomic::remove_temp_files(dir)?);
+        }
+        Ok(removed)
+    }
+
+    /// Takes the cabinet's lock for itself until it's dropped. Fails with `ErrorKind::Locked` if
+    /// any other lock is held on it.
+    fn lock(&self) -> Result<atomic::Lock> {
+        self.take_lock(atomic::Lock::exclusive)
+    }
+
+    /// Takes the cabinet's lock along with other writers, so its temporary files are safe
+    /// until it's dropped. Fails with `ErrorKind::Locked` if the lock is held exclusively.
+    fn lock_shared(&self) -> Result<atomic::Lock> {
+        self.take_lock(atomic::Lock::shared)
+    }
+
+    fn take_lock(&self, take: fn(PathBuf) -> io::Result<atomic::Lock>) -> Result<atomic::Lock> {
+        let dir = self.root.join(CONFIG_DIR);
+        fs::create_dir_all(&dir)?;
+        take(dir.join(LOCK_FILE)).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock => ErrorKind::Locked.into(),
+            _ => e.into(),
+        })
+    }
+
+    /// The documents in the cabinet, leaving out the inbox if it's one of its subfolders.
+    pub fn documents(&self) -> Result<Vec<Document>> {
+        let recursive = self.config.scans_subfolders();
+        let docs = utils::read_docs(&self.root, self.template(), recursive)?;
+        Ok(match self.inbox() {
+            Some(inbox) => docs
+                .into_iter()
+                .filter(|doc| !doc.path.starts_with(&inbox))
+                .collect(),
+            None => docs,
+        })
+    }
+
+    /// The directory new documents arrive in, if the cabinet has one.
+    pub fn inbox(&self) -> Option<PathBuf> {
+        self.config
+            .inbox
+            .as_ref()
+            .map(|inbox| self.root.join(inbox))
+    }
+
+    /// The documents waiting in the inbox.
+    pub fn inbox_documents(&self) -> Result<Vec<Document>> {
+        match self.inbox() {
+            Some(inbox) => utils::read_docs(&inbox, self.template(), false),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Moves a document from the inbox into the cabinet under its normalized name, straight
+    /// into the folder the filing layout gives it if it has one. A file with the same name
+    /// already there is handled according to `policy`. Returns the new path.
+    pub fn archive(&self, doc: &mut Document, policy: ConflictPolicy) -> Result<PathBuf> {
+        let _lock = self.lock_shared()?;
+        doc.normalize_fields();
+        let filename = doc.normalized_filename(self.template());
+        let folder = match &self.config.layout {
+            Some(layout) if utils::is_normalized(&filename, self.template()) => {
+                layout.folder(doc).unwrap_or_default()
+            }
+            _ => String::new(),
+        };
+        let dir = self.root.join(&folder);
+        let replaced = conflict::resolve(doc, self.template(), &dir, policy)?;
+        fs::create_dir_all(&dir)?;
+        if let Some(target) = replaced {
+            self.trash_file(&target)?;
+        }
+        let old = doc.path.clone();
+        let path = doc.move_to(self.template(), &dir)?;
+        doc.folder = folder;
+        self.update_manifest(|manifest| manifest.record(&self.root, self.relative(&path)))?;
+        self.log_move(Action::Move, &old, &path)?;
+        Ok(path)
+    }
+
+    /// Renames the document to the filename its fields produce under this cabinet's template.
+    /// Another file that already has that name is handled according to `policy`. Returns the
+    /// new path.
+    pub fn rename(&self, doc: &mut Document, policy: ConflictPolicy) -> Result<PathBuf> {
+        let _lock = self.lock_shared()?;
+        let old_path = doc.path.clone();
+        let dir = old_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        if let Some(target) = conflict::resolve(doc, self.template(), &dir, policy)? {
+            self.trash_file(&target)?;
+        }
+        let new_path = doc.rename(self.template())?;
+        if new_path != old_path {
+            let (old, new) = (self.relative_path(&old_path), self.relative_path(&new_path));
+            self.update_manifest(|manifest| {
+                manifest.rename(&old, &new);
+                Ok(())
+            })?;
+            self.log_move(Action::Rename, &old_path, &new_path)?;
+        }
+        Ok(new_path)
+    }
+
+    /// Moves the document to the cabinet's trash, from which it can be restored. Returns its
+    /// path in the trash.
+    pub fn delete(&self, doc: &Document) -> Result<PathBuf> {
+        let _lock = self.lock_shared()?;
+        self.trash_file(&doc.path)
+    }
+
+    fn trash_file(&self, path: &Path) -> Result<PathBuf> {
+        let before = FileState::new(&self.root, path)?;
+        let name = before.path.clone();
+        let mut trash = Trash::load(&self.root)?;
+        let trash_name = Trash::free_name(&self.root, &name);
+        fs::create_dir_all(Trash::dir(&self.root))?;
+        let after = FileState {
+            path: Trash::path_of(&trash_name),
+            sha256: before.sha256.clone(),
+        };
+        let trashed = after.file(&self.root);
+        atomic::move_file(path, &trashed)?;
+        trash.add(&trash_name, &name);
+        trash.save(&self.root)?;
+        self.update_manifest(|manifest| {
+            manifest.remove(&name);
+            Ok(())
+        })?;
+        self.log(Operation::new(Action::Trash, before, Some(after)))?;
+        self.purge_trash()?;
+        Ok(trashed)
+    }
+
+    /// Overwrites the document's file with random data and deletes it for good, bypassing the
+    /// trash. `passes` defaults to the cabinet's `shred_passes`.
+    pub fn shred(&self, doc: &Document, passes: Option<u32>) -> Result<()> {
+        let _lock = self.lock_shared()?;
+        let passes = passes
+            .or(self.config.shred_passes)
+            .unwrap_or(shred::DEFAULT_PASSES);
+        let before = FileState::new(&self.root, &doc.path)?;
+        shred::shred_file(&doc.path, passes)?;
+        self.update_manifest(|manifest| {
+            manifest.remove(&before.path);
+            Ok(())
+        })?;
+        self.log(Operation::new(Action::Shred, before, None))
+    }
+
+    /// The documents in the trash, oldest first. Those older than the cabinet's
+    /// `purge_after_days` are purged first.
+    pub fn trash(&self) -> Result<Vec<TrashEntry>> {
+        self.purge_trash()?;
+        Ok(Trash::load(&self.root)?.entries)
+    }
+
+    /// Moves a document from the trash back to where it was. Returns its path.
+    pub fn restore(&self, name: &str) -> Result<PathBuf> {
+        let _lock = self.lock_shared()?;
+        let mut trash = Trash::load(&self.root)?;
+        let entry = match trash.remove(name) {
+            Some(entry) => entry,
+            None => bail!("\"{}\" isn't in the trash", name),
+        };
+        let target = self.root.join(utils::path_from_string(&entry.path));
+        if target.exists() {
+            bail!(ErrorKind::Exists(target.display().to_string()));
+        }
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let source = self.root.join(utils::path_from_string(&entry.trash_path()));
+        atomic::move_file(&source, &target)?;
+        trash.save(&self.root)?;
+        self.update_manifest(|manifest| manifest.record(&self.root, self.relative(&target)))?;
+        self.log_move(Action::Restore, &source, &target)?;
+        Ok(target)
+    }
+
+    /// Permanently deletes the documents in the trash, or only the ones deleted more than
+    /// `days` ago. Returns the deleted entries.
+    pub fn empty_trash(&self, days: Option<u32>) -> Result<Vec<TrashEntry>> {
+        let _lock = self.lock_shared()?;
+        self.remove_from_trash(days, true)
+    }
+
+    /// Purges the documents deleted more than the cabinet's `purge_after_days` ago. The purge
+    /// isn't journaled, as it would hide the operation that set it off from `undo`.
+    fn purge_trash(&self) -> Result<()> {
+        if let Some(days) = self.config.purge_after_days {
+            let _lock = self.lock_shared()?;
+            self.remove_from_trash(Some(days), false)?;
+        }
+        Ok(())
+    }
+
+    fn remove_from_trash(&self, days: Option<u32>, journal: bool) -> Result<Vec<TrashEntry>> {
+        let mut trash = Trash::load(&self.root)?;
+        let entries = match days {
+            Some(days) => trash.expired(days),
+            None => trash.entries.clone(),
+        };
+        for entry in &entries {
+            let path = self.root.join(utils::path_from_string(&entry.trash_path()));
+            let before = self.remove(&path)?;
+            trash.remove(&entry.name);
+            if journal {
+                self.log(Operation::new(Action::Delete, before, None))?;
+            }
+        }
+        trash.save(&self.root)?;
+        Ok(entries)
+    }
+
+    /// Deletes the file at `path` and drops it from the manifest. Returns its last state.
+    fn remove(&self, path: &Path) -> Result<FileState> {
+        let before = FileState::new(&self.root, path)?;
+        fs::remove_file(path)?;
+        self.update_manifest(|manifest| {
+            manifest.remove(&before.path);
+            Ok(())
+        })?;
+        Ok(before)
+    }
+
+    /// Moves a normalized document, along with its other pages, into the folder the filing
+    /// layout gives it, creating folders as needed. A file with the same name already there is
+    /// handled according to `policy`. Each move is recorded as soon as it's made, so if a later
+    /// one fails the earlier ones can still be undone. Returns the old and new path of each
+    /// moved document.
+    pub fn file(&self, doc: &Document, policy: ConflictPolicy) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let _lock = self.lock_shared()?;
+        let cant_file = |reason: &str| -> Error {
+            ErrorKind::CantFile(doc.relative_path(), reason.to_string()).into()
+        };
+        let layout = match &self.config.layout {
+            Some(layout) => layout,
+            None => return Err(cant_file("the cabinet has no filing layout")),
+        };
+        if !doc.is_normalized() {
+            return Err(cant_file("it isn't normalized"));
+        }
+        let folder = layout
+            .folder(doc)
+            .map_err(|field| cant_file(&format!("its {} is empty", field)))?;
+        if doc.folder == folder {
+            return Ok(Vec::new());
+        }
+        let dir = self.root.join(&folder);
+        let mut moved = Vec::new();
+        for mut doc in std::iter::once(doc.clone()).chain(self.companions(doc)?) {
+            let replaced = conflict::resolve(&mut doc, self.template(), &dir, policy)?;
+            fs::create_dir_all(&dir)?;
+            if let Some(target) = replaced {
+                self.trash_file(&target)?;
+            }
+            let old_path = doc.path.clone();
+            let new_path = doc.move_to(self.template(), &dir)?;
+            let (old, new) = (self.relative_path(&old_path), self.relative_path(&new_path));
+            self.update_manifest(|manifest| {
+                manifest.rename(&old, &new);
+                Ok(())
+            })?;
+            self.log_move(Action::Move, &old_path, &new_path)?;
+            moved.push((old_path, new_path));
+        }
+        Ok(moved)
+    }
+
+    /// The other pages of a document: documents in the same folder whose fields only differ
+    /// by page.
+    pub fn companions(&self, doc: &Document) -> Result<Vec<Document>> {
+        Ok(self
+            .documents()?
+            .into_iter()
+            .filter(|other| {
+                other.path != doc.path
+                    && other.folder == doc.folder
+                    && other.page != doc.page
+                    && other.date == doc.date
+                    && other.institution == doc.institution
+                    && other.title == doc.title
+                    && other.fields == doc.fields
+            })
+            .collect())
+    }
+
+    /// Groups the documents whose contents are identical.
+    pub fn duplicates(&self) -> Result<Vec<Vec<Document>>> {
+        Ok(duplicates::find(self.documents()?))
+    }
+
+    /// Deletes every document of a group of duplicates except the one at `keep`.
+    pub fn remove_duplicates(&self, group: &[Document], keep: usize) -> Result<()> {
+        for (i, doc) in group.iter().enumerate() {
+            if i != keep {
+                self.delete(doc)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encrypts the document's file with the cabinet's key, keeping its name and appending
+    /// `.cocoon`. Returns the new path.
+    pub fn encrypt(&self, doc: &mut Document, key: &Key) -> Result<PathBuf> {
+        let _lock = self.lock_shared()?;
+        self.rewrite(doc, Action::Encrypt, |path| crypto::encrypt_file(path, key))
+    }
+
+    /// Decrypts the document's `.cocoon` file with the cabinet's key. Returns the new path.
+    pub fn decrypt(&self, doc: &mut Document, key: &Key) -> Result<PathBuf> {
+        let _lock = self.lock_shared()?;
+        self.rewrite(doc, Action::Decrypt, |path| crypto::decrypt_file(path, key))
+    }
+
+    /// Replaces the document's file with the one `rewrite` makes of it.
+    fn rewrite<F>(&self, doc: &mut Document, action: Action, rewrite: F) -> Result<PathBuf>
+    where
+        F: FnOnce(&Path) -> Result<PathBuf>,
+    {
+        let before = FileState::new(&self.root, &doc.path)?;
+        let path = rewrite(&doc.path)?;
+        self.replace_in_manifest(&before.path, &path)?;
+        let after = FileState::new(&self.root, &path)?;
+        self.log(Operation::new(action, before, Some(after)))?;
+        self.reload(doc, path)
+    }
+
+    fn keyfile_path(&self, name: &str) -> PathBuf {
+        self.root.join(CONFIG_DIR).join(name)
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.keyfile_path(KEYFILE).exists()
+    }
+
+    /// Generates the cabinet's key and stores it wrapped with `passphrase`.
+    pub fn init_key(&self, passphrase: &str) -> Result<Key> {
+        let _lock = self.lock_shared()?;
+        let path = self.keyfile_path(KEYFILE);
+        if path.exists() {
+            bail!(ErrorKind::Exists(path.display().to_string()));
+        }
+        let key = Key::generate()?;
+        fs::create_dir_all(self.root.join(CONFIG_DIR))?;
+        atomic::write(path, &key.wrap(passphrase)?)?;
+        Ok(key)
+    }
+
+    /// Unwraps the cabinet's key with `passphrase`.
+    pub fn unlock(&self, passphrase: &str) -> Result<Key> {
+        let path = self.keyfile_path(KEYFILE);
+        if !path.exists() {
+            bail!(ErrorKind::Crypto("the cabinet has no keyfile".to_string()));
+        }
+        Key::unwrap(&fs::read(path)?, passphrase)
+    }
+
+    /// Re-wraps the cabinet's key with a new passphrase. The documents are left untouched.
+    pub fn change_passphrase(&self, old: &str, new: &str) -> Result<()> {
+        let _lock = self.lock_shared()?;
+        let key = self.unlock(old)?;
+        atomic::replace(self.keyfile_path(KEYFILE), &key.wrap(new)?)?;
+        Ok(())
+    }
+
+    /// Replaces the cabinet's key with a new one and re-encrypts every encrypted document with
+    /// it in parallel, including the ones in the inbox and the trash, calling `progress` after
+    /// each document. Returns the documents that failed; the rotation is only complete, and can
+    /// be run again to resume, if there are none. The cabinet is locked meanwhile.
+    pub fn rotate_key<F>(&self, passphrase: &str, progress: F) -> Result<Vec<(Document, Error)>>
+    where
+        F: Fn(&Document) + Sync,
+    {
+        let old = self.unlock(passphrase)?;
+        let _lock = self.lock()?;
+        let new_path = self.keyfile_path(NEW_KEYFILE);
+        let new = if new_path.exists() {
+            Key::unwrap(&fs::read(&new_path)?, passphrase)?
+        } else {
+            let new = Key::generate()?;
+            atomic::write(&new_path, &new.wrap(passphrase)?)?;
+            new
+        };
+        let (rekeyed, failed): (Vec<_>, Vec<_>) = self
+            .encrypted_documents()?
+            .into_par_iter()
+            .map(|doc| {
+                let result = Self::rekey(&doc, &old, &new);
+                progress(&doc);
+                (doc, result)
+            })
+            .partition(|(_, result)| result.is_ok());
+        let inbox = self.inbox();
+        let trash = Trash::dir(&self.root);
+        self.update_manifest(|manifest| {
+            let recorded = rekeyed.iter().map(|(doc, _)| &doc.path).filter(|path| {
+                !path.starts_with(&trash)
+                    && inbox.as_ref().is_none_or(|inbox| !path.starts_with(inbox))
+            });
+            for path in recorded {
+                manifest.record(&self.root, self.relative(path))?;
+            }
+            Ok(())
+        })?;
+        let mut journal = self.journal()?;
+        for (_, result) in &rekeyed {
+            if let Ok((before, after)) = result {
+                journal.replace_digest(before, after);
+            }
+        }
+        journal.save(&self.root)?;
+        let failed: Vec<(Document, Error)> = failed
+            .into_iter()
+            .filter_map(|(doc, result)| result.err().map(|e| (doc, e)))
+            .collect();
+        if failed.is_empty() {
+            atomicwrites::replace_atomic(&new_path, &self.keyfile_path(KEYFILE))?;
+        }
+        Ok(failed)
+    }
+
+    /// Re-encrypts the document from `old` to `new`. Returns the file's digest before and after.
+    fn rekey(doc: &Document, old: &Key, new: &Key) -> Result<(String, String)> {
+        let before = atomic::checksum(&fs::read(&doc.path)?);
+        crypto::rekey_file(&doc.path, old, new)?;
+        Ok((before, atomic::checksum(&fs::read(&doc.path)?)))
+    }
+
+    /// The encrypted documents of the cabinet, its inbox and its trash, which are all encrypted
+    /// with the cabinet's key. The trash may have renamed them, e.g. to `a.pdf.cocoon.1`.
+    pub fn encrypted_documents(&self) -> Result<Vec<Document>> {
+        let mut docs = self.documents()?;
+        docs.extend(self.inbox_documents()?);
+        docs.retain(|doc| doc.encrypted);
+        for entry in Trash::load(&self.root)?.entries {
+            if crypto::is_encrypted(utils::path_from_string(&entry.path)) {
+                let path = self.root.join(utils::path_from_string(&entry.trash_path()));
+                let mut doc = Document::new(path, self.template())?;
+                doc.folder = TRASH_DIR.to_string();
+                docs.push(doc);
+            }
+        }
+        Ok(docs)
+    }
+
+    /// The path of a document relative to the cabinet, which is how the manifest refers to it.
+    /// Paths that aren't valid UTF-8 are encoded as `utils::path_to_string` does.
+    pub fn relative_path<P: AsRef<Path>>(&self, path: P) -> String {
+        utils::path_to_string(self.relative(path.as_ref()))
+    }
+
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    pub fn manifest(&self) -> Result<Manifest> {
+        Manifest::load(&self.root)
+    }
+
+    /// Records the current contents of `docs` in the manifest, creating it if needed.
+    pub fn record(&self, docs: &[Document]) -> Result<()> {
+        let _lock = self.lock_shared()?;
+        let mut manifest = self.manifest()?;
+        for doc in docs {
+            manifest.record(&self.root, self.relative(&doc.path))?;
+        }
+        manifest.save(&self.root)
+    }
+
+    /// Compares the manifest with the documents on disk.
+    pub fn verify_integrity(&self) -> Result<Report> {
+        let names: Vec<String> = self
+            .documents()?
+            .iter()
+            .map(|doc| self.relative_path(&doc.path))
+            .collect();
+        Ok(self.manifest()?.verify(&self.root, &names))
+    }
+
+    pub fn journal(&self) -> Result<Journal> {
+        Journal::load(&self.root)
+    }
+
+    /// Undoes the last operation of the journal and returns it, or `None` if there's nothing
+    /// to undo. Undoing encryption needs the cabinet's key. An operation that can never be
+    /// undone, such as emptying the trash, fails and is dropped from the journal instead, so
+    /// the ones before it can be undone next.
+    pub fn undo(&self, key: Option<&Key>) -> Result<Option<Operation>> {
+        let _lock = self.lock_shared()?;
+        let mut journal = self.journal()?;
+        let operation = match journal.operations.pop() {
+            Some(operation) => operation,
+            None => return Ok(None),
+        };
+        // Otherwise it would keep every operation before it from being undone.
+        if let Some(reason) = self.irreversible(&operation) {
+            journal.save(&self.root)?;
+            bail!(ErrorKind::History(format!(
+                "can't undo \"{}\": {}, so it was dropped from the history",
+                operation, reason
+            )));
+        }
+        let rewritten = self.replay(&operation, false, key)?;
+        journal.undone.push(operation);
+        if let Some((old, new)) = rewritten {
+            journal.replace_digest(&old, &new);
+        }
+        journal.save(&self.root)?;
+        Ok(journal.undone.last().cloned())
+    }
+
+    /// Why the operation can't be undone whatever happens to the cabinet, if it can't.
+    fn irreversible(&self, operation: &Operation) -> Option<&'static str> {
+        match operation.action {
+            Action::Delete => Some("deleted files can't be restored"),
+            Action::Shred => Some("shredded files can't be restored"),
+            Action::Trash if !operation.after.as_ref()?.file(&self.root).exists() => {
+                Some("the file was removed from the trash since")
+            }
+            _ => None,
+        }
+    }
+
+    /// Does the last undone operation again and returns it, or `None` if there's nothing to
+    /// redo.
+    pub fn redo(&self, key: Option<&Key>) -> Result<Option<Operation>> {
+        let _lock = self.lock_shared()?;
+        let mut journal = self.journal()?;
+        let operation = match journal.undone.pop() {
+            Some(operation) => operation,
+            None => return Ok(None),
+        };
+        let rewritten = self.replay(&operation, true, key)?;
+        journal.operations.push(operation);
+        if let Some((old, new)) = rewritten {
+            journal.replace_digest(&old, &new);
+        }
+        journal.save(&self.root)?;
+        Ok(journal.operations.last().cloned())
+    }
+
+    /// Takes a file from the `before` state of an operation to its `after` state if `forward`,
+    /// or back. Files that changed since the operation are left alone. Encryption isn't
+    /// deterministic, so if the file had to be encrypted again, returns the digest it was
+    /// recorded with and its new one.
+    fn replay(
+        &self,
+        operation: &Operation,
+        forward: bool,
+        key: Option<&Key>,
+    ) -> Result<Option<(String, String)>> {
+        let description = format!(
+            "can't {} \"{}\"",
+            if forward { "redo" } else { "undo" },
+            operation
+        );
+        let fail = |reason: &str| -> Error {
+            ErrorKind::History(format!("{}: {}", description, reason)).into()
+        };
+        let (from, to) = if forward {
+            (Some(operation.before.clone()), operation.after.clone())
+        } else {
+            (operation.after.clone(), Some(operation.before.clone()))
+        };
+        let from = from.ok_or_else(|| fail("there's no file to restore"))?;
+        let source = from.file(&self.root);
+        match FileState::new(&self.root, &source) {
+            Ok(current) if current.sha256 == from.sha256 => {}
+            Ok(_) => return Err(fail(&format!("\"{}\" changed since", from.path))),
+            Err(_) => return Err(fail(&format!("\"{}\" is missing", from.path))),
+        }
+        let to = match to {
+            Some(to) => to,
+            None => return self.remove(&source).map(|_| None),
+        };
+        let target = to.file(&self.root);
+        if target.exists() {
+            return Err(fail(&format!("\"{}\" already exists", to.path)));
+        }
+        let encrypt = match (operation.action, forward) {
+            (Action::Encrypt, true) | (Action::Decrypt, false) => true,
+            (Action::Decrypt, true) | (Action::Encrypt, false) => false,
+            _ => {
+                if let Some(dir) = target.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                atomic::move_file(&source, &target)?;
+                self.replayed_move(&from.path, &to.path)?;
+                if operation.action == Action::Move && !forward {
+                    self.remove_empty_folders(&source)?;
+                }
+                return Ok(None);
+            }
+        };
+        let key = key.ok_or_else(|| fail("the cabinet's key is needed"))?;
+        let path = if encrypt {
+            crypto::encrypt_file(&source, key)?
+        } else {
+            crypto::decrypt_file(&source, key)?
+        };
+        self.replace_in_manifest(&from.path, &path)?;
+        let state = FileState::new(&self.root, &path)?;
+        Ok(Some((to.sha256, state.sha256)).filter(|(old, new)| old != new))
+    }
+
+    /// Keeps the manifest and the trash index up to date after a file was moved from `from` to
+    /// `to`, maybe in or out of the trash.
+    fn replayed_move(&self, from: &str, to: &str) -> Result<()> {
+        let mut trash = Trash::load(&self.root)?;
+        if let Some(name) = Trash::name_of(to) {
+            trash.add(&name, from);
+            trash.save(&self.root)?;
+            self.update_manifest(|manifest| {
+                manifest.remove(from);
+                Ok(())
+            })
+        } else if let Some(name) = Trash::name_of(from) {
+            trash.remove(&name);
+            trash.save(&self.root)?;
+            let to = utils::path_from_string(to);
+            self.update_manifest(|manifest| manifest.record(&self.root, &to))
+        } else {
+            self.update_manifest(|manifest| {
+                manifest.rename(from, to);
+                Ok(())
+            })
+        }
+    }
+
+    /// Removes the folders that `path` was in if they're left empty, e.g. the layout's folders
+    /// after undoing a filing, up to the cabinet's root or its inbox.
+    fn remove_empty_folders(&self, path: &Path) -> Result<()> {
+        let inbox = self.inbox();
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) || dir == self.root || Some(dir) == inbox.as_deref() {
+                break;
+            }
+            if fs::read_dir(dir)?.next().is_some() {
+                break;
+            }
+            fs::remove_dir(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Records an operation in the journal.
+    fn log(&self, operation: Operation) -> Result<()> {
+        let mut journal = self.journal()?;
+        journal.record(operation);
+        journal.save(&self.root)
+    }
+
+    /// Records the rename of a file whose contents didn't change.
+    fn log_move(&self, action: Action, old: &Path, new: &Path) -> Result<()> {
+        let after = FileState::new(&self.root, new)?;
+        let before = FileState {
+            path: self.relative_path(old),
+            sha256: after.sha256.clone(),
+        };
+        self.log(Operation::new(action, before, Some(after)))
+    }
+
+    /// Applies `update` to the manifest, if the cabinet has one, and saves it.
+    fn update_manifest<F>(&self, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut Manifest) -> io::Result<()>,
+    {
+        if !Manifest::path(&self.root).exists() {
+            return Ok(());
+        }
+        let mut manifest = self.manifest()?;
+        update(&mut manifest)?;
+        manifest.save(&self.root)
+    }
+
+    /// Replaces the entry of a document whose file was rewritten to `path`.
+    fn replace_in_manifest(&self, old: &str, path: &Path) -> Result<()> {
+        self.update_manifest(|manifest| {
+            manifest.remove(old);
+            manifest.record(&self.root, self.relative(path))
+        })
+    }
+
+    /// Points `doc` at the file that replaced it.
+    fn reload(&self, doc: &mut Document, path: PathBuf) -> Result<PathBuf> {
+        let folder = std::mem::take(&mut doc.folder);
+        *doc = Document::new(path.clone(), self.template())?;
+        doc.folder = folder;
+        Ok(path)
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let name = OsStr::from_bytes(b"Rechnung_M\xfcller.pdf");
+    fs::write(root.join(name), "invoice").unwrap();
+    let cabinet = Cabinet::open(root).unwrap();
+    let docs = cabinet.documents().unwrap();
+    cabinet.record(&docs).unwrap();
+    assert!(cabinet.verify_integrity().unwrap().is_ok());
+
+    cabinet.delete(&docs[0]).unwrap();
+    assert!(!root.join(name).exists());
+    let trash = cabinet.trash().unwrap();
+    assert_eq!(utils::path_from_string(&trash[0].path), Path::new(name));
+    cabinet.undo(None).unwrap();
+    assert_eq!(fs::read(root.join(name)).unwrap(), b"invoice");
+    assert!(cabinet.verify_integrity().unwrap().is_ok());
+}
+
+#[test]
+fn test_undo_after_purge() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    fs::write(root.join("2020-01-01_Bank_Statement_1.pdf"), "old").unwrap();
+    fs::write(root.join("2020-02-01_Bank_Statement_1.pdf"), "new").unwrap();
+    let mut cabinet = Cabinet::open(root).unwrap();
+    cabinet
+        .set_config(Config {
+            purge_after_days: Some(30),
+            ..Config::default()
+        })
+        .unwrap();
+    let mut docs = cabinet.documents().unwrap();
+    docs.sort_by(|a, b| a.path.cmp(&b.path));
+    let (old, new) = (&docs[0], &docs[1]);
+    cabinet.delete(old).unwrap();
+    let mut trash = Trash::load(root).unwrap();
+    trash.entries[0].deleted = "2020-01-01T00:00:00+00:00".to_string();
+    trash.save(root).unwrap();
+
+    // Deleting purges the old document without hiding the deletion from undo.
+    cabinet.delete(new).unwrap();
+    assert_eq!(cabinet.trash().unwrap().len(), 1);
+    cabinet.undo(None).unwrap();
+    assert_eq!(fs::read(&new.path).unwrap(), b"new");
+}
+
+#[test]
+fn test_undo_past_deletion() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    fs::write(root.join("scan.pdf"), "statement").unwrap();
+    fs::write(root.join("2020-01-01_Bank_Statement_1.pdf"), "old").unwrap();
+    let cabinet = Cabinet::open(root).unwrap();
+    let mut docs = cabinet.documents().unwrap();
+    docs.sort_by(|a, b| a.path.cmp(&b.path));
+    let (mut scan, old) = (docs.pop().unwrap(), docs.pop().unwrap());
+    scan.date = "2020-02-01".to_string();
+    scan.institution = "Bank".to_string();
+    scan.title = "Statement".to_string();
+    cabinet.rename(&mut scan, ConflictPolicy::Cancel).unwrap();
+    cabinet.delete(&old).unwrap();
+    cabinet.empty_trash(None).unwrap();
+
+    // The deletion and the move into the trash are dropped one at a time.
+    for _ in 0..2 {
+        match cabinet.undo(None) {
+            Err(Error(ErrorKind::History(_), _)) => {}
+            result => panic!("{:?}", result),
+        }
+    }
+    assert_eq!(cabinet.undo(None).unwrap().unwrap().action, Action::Rename);
+    assert_eq!(fs::read(root.join("scan.pdf")).unwrap(), b"statement");
+    assert!(cabinet.undo(None).unwrap().is_none());
+}
+
+#[test]
+fn test_undo_after_rotate_key() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let path = root.join("2020-01-01_Bank_Statement_1.pdf");
+    fs::write(&path, "statement").unwrap();
+    let cabinet = Cabinet::open(root).unwrap();
+    let mut doc = cabinet.documents().unwrap().remove(0);
+    let key = cabinet.init_key("secret").unwrap();
+    cabinet.encrypt(&mut doc, &key).unwrap();
+    assert!(cabinet.rotate_key("secret", |_| {}).unwrap().is_empty());
+    let key = cabinet.unlock("secret").unwrap();
+    cabinet.undo(Some(&key)).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"statement");
+    assert!(!doc.path.exists());
+}
+
+#[test]
+fn test_rotate_key_in_trash_and_inbox() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    fs::create_dir(root.join("inbox")).unwrap();
+    fs::write(root.join("2020-01-01_Bank_Statement_1.pdf"), "statement").unwrap();
+    fs::write(root.join("inbox/scan.pdf"), "scan").unwrap();
+    let mut cabinet = Cabinet::open(root).unwrap();
+    cabinet
+        .set_config(Config {
+            inbox: Some("inbox".to_string()),
+            ..Config::default()
+        })
+        .unwrap();
+    let key = cabinet.init_key("secret").unwrap();
+    let mut doc = cabinet.documents().unwrap().remove(0);
+    cabinet.encrypt(&mut doc, &key).unwrap();
+    cabinet.delete(&doc).unwrap();
+    let mut scan = cabinet.inbox_documents().unwrap().remove(0);
+    cabinet.encrypt(&mut scan, &key).unwrap();
+
+    assert_eq!(cabinet.encrypted_documents().unwrap().len(), 2);
+    assert!(cabinet.rotate_key("secret", |_| {}).unwrap().is_empty());
+    let key = cabinet.unlock("secret").unwrap();
+    let name = cabinet.trash().unwrap()[0].name.clone();
+    cabinet.restore(&name).unwrap();
+    let mut doc = cabinet.documents().unwrap().remove(0);
+    cabinet.decrypt(&mut doc, &key).unwrap();
+    assert_eq!(fs::read(&doc.path).unwrap(), b"statement");
+    cabinet.decrypt(&mut scan, &key).unwrap();
+    assert_eq!(fs::read(&scan.path).unwrap(), b"scan");
+}
+
+#[test]
+fn test_file_records_each_move() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    fs::create_dir(root.join("2020")).unwrap();
+    fs::write(root.join("2020/2020-01-01_Bank_Statement_2.pdf"), "other").unwrap();
+    fs::write(root.join("2020-01-01_Bank_Statement_1.pdf"), "page 1").unwrap();
+    fs::write(root.join("2020-01-01_Bank_Statement_2.pdf"), "page 2").unwrap();
+    let mut cabinet = Cabinet::open(root).unwrap();
+    let layout = Some(crate::layout::Layout::parse("{year}").unwrap());
+    cabinet
+        .set_config(Config {
+            layout,
+            ..Config::default()
+        })
+        .unwrap();
+    let docs = cabinet.documents().unwrap();
+    let page = |n: &str| {
+        docs.iter()
+            .find(|doc| doc.folder.is_empty() && doc.page == n)
+    };
+
+    // The first page is moved before the second one runs into the existing file, and can
+    // still be undone.
+    let first = page("1").unwrap();
+    assert!(cabinet.file(first, ConflictPolicy::Cancel).is_err());
+    assert!(root.join("2020/2020-01-01_Bank_Statement_1.pdf").exists());
+    cabinet.undo(None).unwrap();
+    assert_eq!(fs::read(&first.path).unwrap(), b"page 1");
+
+    let moved = cabinet.file(first, ConflictPolicy::Increment).unwrap();
+    assert_eq!(moved.len(), 2);
+    assert_eq!(
+        moved[1].1,
+        root.join("2020/2020-01-01_Bank_Statement_3.pdf")
+    );
+    assert_eq!(fs::read(&moved[1].1).unwrap(), b"page 2");
+}
+
+#[test]
+fn test_undo_file_removes_empty_folders() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    fs::create_dir(root.join("2020")).unwrap();
+    fs::write(root.join("2020/2020-01-01_Chase_Statement_1.pdf"), "other").unwrap();
+    fs::write(root.join("2020-01-01_Bank_Statement_1.pdf"), "statement").unwrap();
+    let mut cabinet = Cabinet::open(root).unwrap();
+    let layout = Some(crate::layout::Layout::parse("{year}/{institution}").unwrap());
+    cabinet
+        .set_config(Config {
+            layout,
+            recursive: true,
+            ..Config::default()
+        })
+        .unwrap();
+    let doc = cabinet
+        .documents()
+        .unwrap()
+        .into_iter()
+        .find(|doc| doc.folder.is_empty())
+        .unwrap();
+    cabinet.file(&doc, ConflictPolicy::Cancel).unwrap();
+    assert!(root
+        .join("2020/Bank/2020-01-01_Bank_Statement_1.pdf")
+        .exists());
+
+    // The folder the filing created goes away, the one that still has a document stays.
+    cabinet.undo(None).unwrap();
+    assert!(doc.path.exists());
+    assert!(!root.join("2020/Bank").exists());
+    assert!(root.join("2020").exists());
+
+    cabinet.redo(None).unwrap();
+    assert!(root
+        .join("2020/Bank/2020-01-01_Bank_Statement_1.pdf")
+        .exists());
+}
+
+#[test]
+fn test_remove_temp_files() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    fs::create_dir_all(root.join(CONFIG_DIR)).unwrap();
+    fs::create_dir(root.join("2020")).unwrap();
+    let temp = atomic::temp_path(root.join("2020/2020-01-01_Bank_Statement_1.pdf"));
+    fs::write(&temp, "partial").unwrap();
+    let mut cabinet = Cabinet::open(root).unwrap();
+    cabinet
+        .set_config(Config {
+            recursive: true,
+            ..Config::default()
+        })
+        .unwrap();
+
+    // Another process may be writing it.
+    let lock = cabinet.lock_shared().unwrap();
+    assert!(cabinet.remove_temp_files().unwrap().is_empty());
+    assert!(temp.exists());
+    drop(lock);
+    let lock = cabinet.lock().unwrap();
+    match cabinet.record(&[]) {
+        Err(Error(ErrorKind::Locked, _)) => {}
+        result => panic!("{:?}", result),
+    }
+    drop(lock);
+    assert_eq!(cabinet.remove_temp_files().unwrap(), vec![temp.clone()]);
+    assert!(!temp.exists());
+}
@@ -0,0 +1,1526 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use filecabinet::crypto::Key;
+use filecabinet::duplicates;
+use filecabinet::export::{self, Format};
+use filecabinet::layout::Layout;
+use filecabinet::utils::{self, OptDoc};
+use filecabinet::{
+    Cabinet, ConflictPolicy, Document, Filter, Matcher, Query, SortKey, SortOrder, Template,
+};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+const EXIT_OK: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+/// Environment variables the passphrases are read from before prompting for them.
+const PASSPHRASE_VAR: &str = "FILECABINET_PASSPHRASE";
+const NEW_PASSPHRASE_VAR: &str = "FILECABINET_NEW_PASSPHRASE";
+
+const PASSPHRASE_HELP: &str =
+    "The passphrase is read from FILECABINET_PASSPHRASE, or prompted for if it isn't set.";
+const INIT_PASSPHRASE_HELP: &str = "The passphrase is read from FILECABINET_PASSPHRASE, or \
+    prompted for twice if it isn't set.";
+const NEW_PASSPHRASE_HELP: &str = "The passphrase is read from FILECABINET_PASSPHRASE and \
+    the new one from FILECABINET_NEW_PASSPHRASE, or prompted for if they aren't set, the new \
+    one twice.";
+
+/// Builds the command-line interface. Running without a subcommand launches the GUI.
+pub fn app<'a, 'b>(version: &'b str) -> App<'a, 'b> {
+    let dir = Arg::with_name("dir")
+        .short("d")
+        .long("dir")
+        .takes_value(true)
+        .default_value(".")
+        .help("Path to the documents");
+    let file = Arg::with_name("file")
+        .required(true)
+        .help("Filename of the document, relative to --dir");
+    let on_conflict = Arg::with_name("on-conflict")
+        .long("on-conflict")
+        .takes_value(true)
+        .possible_values(&["cancel", "increment", "overwrite"])
+        .default_value("cancel")
+        .help(
+            "What to do if a file already has the new name: leave both alone, bump the page \
+             number, or replace the file if it's identical",
+        );
+    App::new("filecabinet")
+        .version(version)
+        .setting(AppSettings::VersionlessSubcommands)
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Lists the documents in the directory")
+                .arg(dir.clone())
+                .arg(
+                    Arg::with_name("filter")
+                        .short("f")
+                        .long("filter")
+                        .takes_value(true)
+                        .possible_values(&["all", "normalized", "unnormalized"])
+                        .default_value("all"),
+                )
+                .arg(
+                    Arg::with_name("where")
+                        .short("w")
+                        .long("where")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Only list documents whose field has a value, e.g. owner=alex"),
+                )
+                .arg(
+                    Arg::with_name("search")
+                        .short("s")
+                        .long("search")
+                        .takes_value(true)
+                        .help(
+                            "Only list documents matching a search, e.g. 'chase year:2021 ext:pdf'",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("folder")
+                        .long("folder")
+                        .takes_value(true)
+                        .help(
+                            "Only list documents in a folder and its subfolders, e.g. 2021/Chase",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("inbox")
+                        .long("inbox")
+                        .help("List the documents waiting in the inbox instead"),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .takes_value(true)
+                        .possible_values(&[
+                            "date",
+                            "institution",
+                            "title",
+                            "page",
+                            "size",
+                            "modified",
+                        ])
+                        .help("Sorts the documents, keeping the pages of each in order"),
+                )
+                .arg(
+                    Arg::with_name("descending")
+                        .long("descending")
+                        .requires("sort")
+                        .help("Sorts in descending order"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("show")
+                .about("Shows the fields parsed from a document's filename")
+                .arg(dir.clone())
+                .arg(file.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("rename")
+                .about("Sets fields of a document and renames it to its normalized name")
+                .arg(dir.clone())
+                .arg(on_conflict.clone())
+                .arg(file.clone())
+                .arg(Arg::with_name("date").long("date").takes_value(true))
+                .arg(
+                    Arg::with_name("institution")
+                        .long("institution")
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("title").long("title").takes_value(true))
+                .arg(Arg::with_name("page").long("page").takes_value(true))
+                .arg(
+                    Arg::with_name("set")
+                        .long("set")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Sets a custom field, e.g. --set owner=alex"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("normalize")
+                .about("Renames documents whose fields can be parsed to their normalized name")
+                .arg(dir.clone())
+                .arg(on_conflict.clone())
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Only print what would be renamed"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .multiple(true)
+                        .help("Documents to normalize, defaults to all unnormalized ones"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("delete")
+                .about("Moves documents to the cabinet's trash")
+                .arg(dir.clone())
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Confirm the deletion"),
+                )
+                .arg(file.clone().multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("shred")
+                .about("Overwrites documents with random data and deletes them for good")
+                .arg(dir.clone())
+                .arg(
+                    Arg::with_name("passes")
+                        .long("passes")
+                        .takes_value(true)
+                        .help("Times to overwrite each document, defaults to the cabinet's config"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Confirm the deletion"),
+                )
+                .arg(file.clone().multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("duplicates")
+                .about("Lists groups of documents with identical contents")
+                .arg(dir.clone())
+                .arg(Arg::with_name("delete").long("delete").help(
+                    "Keep one copy of each group, preferably a normalized one, and delete the rest",
+                ))
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Confirm the deletion"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trash")
+                .about("Lists, restores or permanently deletes the documents in the trash")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists the documents in the trash, oldest first")
+                        .arg(dir.clone()),
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("Moves documents from the trash back to where they were")
+                        .arg(dir.clone())
+                        .arg(
+                            Arg::with_name("names")
+                                .required(true)
+                                .multiple(true)
+                                .help("Names of the documents in the trash"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("empty")
+                        .about("Permanently deletes the documents in the trash")
+                        .arg(dir.clone())
+                        .arg(
+                            Arg::with_name("older-than")
+                                .long("older-than")
+                                .takes_value(true)
+                                .value_name("DAYS")
+                                .help("Only delete documents deleted more than DAYS ago"),
+                        )
+                        .arg(
+                            Arg::with_name("yes")
+                                .short("y")
+                                .long("yes")
+                                .help("Confirm the deletion"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes the catalog of all documents as JSON Lines or CSV")
+                .arg(dir.clone())
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["jsonl", "csv"])
+                        .default_value("jsonl"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("File to write to, defaults to stdout"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Shows or changes the cabinet's config")
+                .arg(dir.clone())
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .takes_value(true)
+                        .help("Filename template, e.g. \"{institution}-{date}-{title}-{page?}\""),
+                )
+                .arg(
+                    Arg::with_name("field")
+                        .long("field")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Custom field the template can use, repeat for each field"),
+                )
+                .arg(
+                    Arg::with_name("recursive")
+                        .long("recursive")
+                        .takes_value(true)
+                        .possible_values(&["true", "false"])
+                        .help("Whether documents in subfolders are part of the cabinet"),
+                )
+                .arg(
+                    Arg::with_name("layout")
+                        .long("layout")
+                        .takes_value(true)
+                        .help("Folders documents are filed into, e.g. \"{year}/{institution}\""),
+                )
+                .arg(
+                    Arg::with_name("inbox")
+                        .long("inbox")
+                        .takes_value(true)
+                        .help("Directory new documents arrive in, relative to --dir"),
+                )
+                .arg(
+                    Arg::with_name("purge-after")
+                        .long("purge-after")
+                        .takes_value(true)
+                        .value_name("DAYS")
+                        .help("Days after which deleted documents are removed from the trash"),
+                )
+                .arg(
+                    Arg::with_name("shred-passes")
+                        .long("shred-passes")
+                        .takes_value(true)
+                        .help("Times shredded documents are overwritten before they're deleted"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("archive")
+                .about(
+                    "Moves documents from the inbox into the cabinet under their normalized name",
+                )
+                .arg(dir.clone())
+                .arg(on_conflict.clone())
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Only print the new names"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .multiple(true)
+                        .help("Documents in the inbox to archive, defaults to all of them"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("file")
+                .about("Moves normalized documents and their other pages into the layout's folders")
+                .arg(dir.clone())
+                .arg(on_conflict)
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Only print where documents would be filed"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .multiple(true)
+                        .help("Documents to file, defaults to all normalized ones"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("encrypt")
+                .about("Encrypts documents to .cocoon files")
+                .after_help(PASSPHRASE_HELP)
+                .arg(dir.clone())
+                .arg(file.clone().multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("decrypt")
+                .about("Decrypts .cocoon documents")
+                .after_help(PASSPHRASE_HELP)
+                .arg(dir.clone())
+                .arg(file.multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("key")
+                .about("Manages the key that documents are encrypted with")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("init")
+                        .about("Creates the cabinet's key, wrapped with a passphrase")
+                        .after_help(INIT_PASSPHRASE_HELP)
+                        .arg(dir.clone()),
+                )
+                .subcommand(
+                    SubCommand::with_name("passwd")
+                        .about("Changes the passphrase without re-encrypting any document")
+                        .after_help(NEW_PASSPHRASE_HELP)
+                        .arg(dir.clone()),
+                )
+                .subcommand(
+                    SubCommand::with_name("rotate")
+                        .about("Replaces the key and re-encrypts every encrypted document")
+                        .after_help(PASSPHRASE_HELP)
+                        .arg(dir.clone()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("manifest")
+                .about("Records the SHA-256 digest, size and mtime of documents in the manifest")
+                .arg(dir.clone())
+                .arg(
+                    Arg::with_name("files")
+                        .multiple(true)
+                        .help("Documents to record, defaults to the ones that aren't recorded yet"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("Lists the operations recorded in the journal, oldest first")
+                .arg(dir.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Undoes the last operations in the journal")
+                .arg(dir.clone())
+                .arg(
+                    Arg::with_name("count")
+                        .default_value("1")
+                        .help("Number of operations to undo"),
+                )
+                .after_help(PASSPHRASE_HELP),
+        )
+        .subcommand(
+            SubCommand::with_name("redo")
+                .about("Does the last undone operations again")
+                .arg(dir.clone())
+                .arg(
+                    Arg::with_name("count")
+                        .default_value("1")
+                        .help("Number of operations to redo"),
+                )
+                .after_help(PASSPHRASE_HELP),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Exits with an error if any document is not normalized")
+                .arg(dir)
+                .arg(
+                    Arg::with_name("integrity")
+                        .long("integrity")
+                        .help("Check the documents against the manifest instead"),
+                ),
+        )
+}
+
+/// Runs the subcommand in `matches` and returns the process exit code.
+pub fn run(matches: &ArgMatches) -> i32 {
+    match matches.subcommand() {
+        ("list", Some(m)) => list(m),
+        ("show", Some(m)) => show(m),
+        ("rename", Some(m)) => rename(m),
+        ("normalize", Some(m)) => normalize(m),
+        ("delete", Some(m)) => delete(m),
+        ("shred", Some(m)) => shred(m),
+        ("duplicates", Some(m)) => find_duplicates(m),
+        ("trash", Some(m)) => match m.subcommand() {
+            ("list", Some(m)) => trash_list(m),
+            ("restore", Some(m)) => trash_restore(m),
+            ("empty", Some(m)) => trash_empty(m),
+            _ => EXIT_USAGE,
+        },
+        ("export", Some(m)) => export(m),
+        ("config", Some(m)) => config(m),
+        ("file", Some(m)) => file(m),
+        ("archive", Some(m)) => archive(m),
+        ("encrypt", Some(m)) => encrypt(m, true),
+        ("decrypt", Some(m)) => encrypt(m, false),
+        ("key", Some(m)) => match m.subcommand() {
+            ("init", Some(m)) => key_init(m),
+            ("passwd", Some(m)) => key_passwd(m),
+            ("rotate", Some(m)) => key_rotate(m),
+            _ => EXIT_USAGE,
+        },
+        ("manifest", Some(m)) => manifest(m),
+        ("history", Some(m)) => history(m),
+        ("undo", Some(m)) => undo(m, false),
+        ("redo", Some(m)) => undo(m, true),
+        ("verify", Some(m)) => verify(m),
+        _ => {
+            eprintln!("{}", matches.usage());
+            EXIT_USAGE
+        }
+    }
+}
+
+/// Opens the cabinet and removes what interrupted writes left behind, which only warns if it
+/// fails.
+fn open(m: &ArgMatches) -> Option<Cabinet> {
+    let dir = m.value_of("dir").unwrap();
+    match Cabinet::open(dir) {
+        Ok(cabinet) => {
+            if let Err(e) = cabinet.remove_temp_files() {
+                eprintln!("warning: could not remove temporary files: {}", e);
+            }
+            Some(cabinet)
+        }
+        Err(e) => {
+            eprintln!("error: could not open \"{}\": {}", dir, e);
+            None
+        }
+    }
+}
+
+/// Sorts documents by path, printing an error if they couldn't be read.
+fn read_docs(docs: filecabinet::Result<Vec<Document>>) -> Option<Vec<Document>> {
+    match docs {
+        Ok(mut docs) => {
+            docs.sort_by(|a, b| a.path.cmp(&b.path));
+            Some(docs)
+        }
+        Err(e) => {
+            eprintln!("error: could not read the documents: {}", e);
+            None
+        }
+    }
+}
+
+/// Splits `name=value`, printing an error if there's no `=`.
+fn parse_assignment(text: &str) -> Option<(&str, &str)> {
+    let mut parts = text.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => Some((name, value)),
+        _ => {
+            eprintln!("error: expected NAME=VALUE, got \"{}\"", text);
+            None
+        }
+    }
+}
+
+/// Finds the document named `name`, printing an error if there is none.
+fn find_doc(docs: &[Document], name: &OsStr) -> Option<Document> {
+    // Documents are found by their path relative to the cabinet, or else by their file name,
+    // which is compared as is so that names that aren't valid UTF-8 can be given too.
+    let path = Path::new(name);
+    let path = path.strip_prefix(".").unwrap_or(path);
+    let doc = docs
+        .iter()
+        .find(|doc| Path::new(&doc.relative_path()) == path)
+        .or_else(|| {
+            docs.iter()
+                .find(|doc| path.file_name().is_some() && doc.path.file_name() == path.file_name())
+        })
+        .cloned();
+    if doc.is_none() {
+        eprintln!("error: no document named \"{}\"", path.display());
+    }
+    doc
+}
+
+fn list(m: &ArgMatches) -> i32 {
+    let filter = match m.value_of("filter") {
+        Some("normalized") => Filter::Normalized,
+        Some("unnormalized") => Filter::Unnormalized,
+        _ => Filter::All,
+    };
+    let conditions = match m.values_of("where") {
+        Some(values) => match values.map(parse_assignment).collect() {
+            Some(conditions) => conditions,
+            None => return EXIT_USAGE,
+        },
+        None => Vec::new(),
+    };
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let folder = m.value_of("folder").unwrap_or_default();
+    let query = Query::parse(m.value_of("search").unwrap_or_default());
+    let docs = if m.is_present("inbox") {
+        cabinet.inbox_documents()
+    } else {
+        cabinet.documents()
+    };
+    let mut docs = match read_docs(docs) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let key = m
+        .value_of("sort")
+        .and_then(|name| SortKey::ALL.iter().find(|key| key.name() == name));
+    if let Some(&key) = key {
+        let descending = m.is_present("descending");
+        SortOrder { key, descending }.sort(&mut docs, |doc| doc);
+    }
+    let matcher = filter.and(query);
+    let matching = docs.iter().filter(|doc| {
+        matcher.matches(doc)
+            && doc.is_in(folder)
+            && conditions.iter().all(|(name, value)| {
+                doc.field(name)
+                    .is_some_and(|v| v.eq_ignore_ascii_case(value))
+            })
+    });
+    for doc in matching {
+        println!("{}", doc.relative_path());
+    }
+    EXIT_OK
+}
+
+fn show(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let doc = match find_doc(&docs, m.value_of_os("file").unwrap()) {
+        Some(doc) => doc,
+        None => return EXIT_FAILURE,
+    };
+    println!("path:        {}", doc.path.display());
+    println!("folder:      {}", doc.folder);
+    println!("date:        {}", doc.date);
+    println!("institution: {}", doc.institution);
+    println!("title:       {}", doc.title);
+    println!("page:        {}", doc.page);
+    for (name, value) in &doc.fields {
+        println!("{:13}{}", format!("{}:", name), value);
+    }
+    println!("extension:   {}", doc.extension);
+    println!("encrypted:   {}", doc.encrypted);
+    println!("normalized:  {}", doc.is_normalized());
+    EXIT_OK
+}
+
+fn conflict_policy(m: &ArgMatches) -> ConflictPolicy {
+    match m.value_of("on-conflict") {
+        Some("increment") => ConflictPolicy::Increment,
+        Some("overwrite") => ConflictPolicy::Overwrite,
+        _ => ConflictPolicy::Cancel,
+    }
+}
+
+fn rename_doc(cabinet: &Cabinet, doc: &mut Document, policy: ConflictPolicy) -> i32 {
+    let old_path = doc.path.clone();
+    match cabinet.rename(doc, policy) {
+        Ok(new_path) => {
+            println!("{} -> {}", old_path.display(), new_path.display());
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("error: could not rename \"{}\": {}", old_path.display(), e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn rename(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let mut doc = match find_doc(&docs, m.value_of_os("file").unwrap()) {
+        Some(doc) => doc,
+        None => return EXIT_FAILURE,
+    };
+    if let Some(date) = m.value_of("date") {
+        doc.date = date.to_string();
+    }
+    if let Some(institution) = m.value_of("institution") {
+        doc.institution = institution.to_string();
+    }
+    if let Some(title) = m.value_of("title") {
+        doc.title = title.to_string();
+    }
+    if let Some(page) = m.value_of("page") {
+        doc.page = page.to_string();
+    }
+    for assignment in m.values_of("set").into_iter().flatten() {
+        match parse_assignment(assignment) {
+            Some((name, _)) if !cabinet.template().has_field(name) => {
+                eprintln!("error: the template has no field \"{}\"", name);
+                return EXIT_USAGE;
+            }
+            Some((name, value)) => doc.set_field(name, value.to_string()),
+            None => return EXIT_USAGE,
+        }
+    }
+    rename_doc(&cabinet, &mut doc, conflict_policy(m))
+}
+
+fn normalize(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let template = cabinet.template();
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let selected: Vec<Document> = match m.values_of_os("files") {
+        Some(names) => match names.map(|name| find_doc(&docs, name)).collect() {
+            Some(selected) => selected,
+            None => return EXIT_FAILURE,
+        },
+        None => docs
+            .into_iter()
+            .filter(|doc| !doc.is_normalized())
+            .collect(),
+    };
+    let mut code = EXIT_OK;
+    for mut doc in selected {
+        // Only rename documents whose filename holds every field, so that nothing is renamed
+        // with made-up values.
+        // The page defaults to 1 if it's missing.
+        let missing: Vec<&str> = OptDoc::new(&doc.path, template)
+            .missing(template)
+            .into_iter()
+            .filter(|&field| field != "page")
+            .collect();
+        if doc.is_normalized() {
+            continue;
+        } else if !missing.is_empty() {
+            eprintln!(
+                "skipped \"{}\": missing {}",
+                doc.relative_path(),
+                missing.join(", ")
+            );
+            code = EXIT_FAILURE;
+        } else if m.is_present("dry-run") {
+            println!(
+                "{} -> {}",
+                doc.relative_path(),
+                doc.normalized_filename(template)
+            );
+        } else if rename_doc(&cabinet, &mut doc, conflict_policy(m)) != EXIT_OK {
+            code = EXIT_FAILURE;
+        }
+    }
+    code
+}
+
+fn delete(m: &ArgMatches) -> i32 {
+    if !m.is_present("yes") {
+        eprintln!("error: refusing to delete without --yes");
+        return EXIT_USAGE;
+    }
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let mut code = EXIT_OK;
+    for name in m.values_of_os("file").unwrap() {
+        let doc = match find_doc(&docs, name) {
+            Some(doc) => doc,
+            None => {
+                code = EXIT_FAILURE;
+                continue;
+            }
+        };
+        match cabinet.delete(&doc) {
+            Ok(_) => println!("moved {} to the trash", doc.path.display()),
+            Err(e) => {
+                eprintln!("error: could not delete \"{}\": {}", doc.path.display(), e);
+                code = EXIT_FAILURE;
+            }
+        }
+    }
+    code
+}
+
+fn shred(m: &ArgMatches) -> i32 {
+    if !m.is_present("yes") {
+        eprintln!("error: refusing to shred without --yes");
+        return EXIT_USAGE;
+    }
+    let passes = match m.value_of("passes").map(str::parse) {
+        Some(Ok(0)) | Some(Err(_)) => {
+            eprintln!("error: --passes must be a number of at least 1");
+            return EXIT_USAGE;
+        }
+        Some(Ok(passes)) => Some(passes),
+        None => None,
+    };
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let mut code = EXIT_OK;
+    for name in m.values_of_os("file").unwrap() {
+        let doc = match find_doc(&docs, name) {
+            Some(doc) => doc,
+            None => {
+                code = EXIT_FAILURE;
+                continue;
+            }
+        };
+        match cabinet.shred(&doc, passes) {
+            Ok(()) => println!("shredded {}", doc.path.display()),
+            Err(e) => {
+                eprintln!("error: could not shred \"{}\": {}", doc.path.display(), e);
+                code = EXIT_FAILURE;
+            }
+        }
+    }
+    code
+}
+
+fn find_duplicates(m: &ArgMatches) -> i32 {
+    let delete = m.is_present("delete");
+    if delete && !m.is_present("yes") {
+        eprintln!("error: refusing to delete without --yes");
+        return EXIT_USAGE;
+    }
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let mut code = EXIT_OK;
+    let groups = match cabinet.duplicates() {
+        Ok(groups) => groups,
+        Err(e) => {
+            eprintln!("error: could not find duplicates: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let keep = duplicates::preferred(group);
+        for (j, doc) in group.iter().enumerate() {
+            if !delete {
+                println!("{}", doc.relative_path());
+            } else if j == keep {
+                println!("kept {}", doc.path.display());
+            } else {
+                match cabinet.delete(doc) {
+                    Ok(_) => println!("moved {} to the trash", doc.path.display()),
+                    Err(e) => {
+                        eprintln!("error: could not delete \"{}\": {}", doc.path.display(), e);
+                        code = EXIT_FAILURE;
+                    }
+                }
+            }
+        }
+    }
+    code
+}
+
+fn trash_list(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    match cabinet.trash() {
+        Ok(entries) => {
+            for entry in entries {
+                println!(
+                    "{}\t{}\t{}",
+                    utils::path_from_string(&entry.name).display(),
+                    utils::path_from_string(&entry.path).display(),
+                    entry.deleted
+                );
+            }
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("error: could not read the trash: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn trash_restore(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let mut code = EXIT_OK;
+    for name in m.values_of_os("names").unwrap() {
+        match cabinet.restore(&utils::path_to_string(name)) {
+            Ok(path) => println!("restored {}", path.display()),
+            Err(e) => {
+                let name = Path::new(name).display();
+                eprintln!("error: could not restore \"{}\": {}", name, e);
+                code = EXIT_FAILURE;
+            }
+        }
+    }
+    code
+}
+
+fn trash_empty(m: &ArgMatches) -> i32 {
+    if !m.is_present("yes") {
+        eprintln!("error: refusing to delete without --yes");
+        return EXIT_USAGE;
+    }
+    let days = match m.value_of("older-than").map(str::parse) {
+        Some(Ok(days)) => Some(days),
+        Some(Err(_)) => {
+            eprintln!("error: invalid number of days");
+            return EXIT_USAGE;
+        }
+        None => None,
+    };
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    match cabinet.empty_trash(days) {
+        Ok(entries) => {
+            for entry in entries {
+                println!("deleted {}", utils::path_from_string(&entry.path).display());
+            }
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("error: could not empty the trash: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Reads a passphrase from the environment variable `var`, or prompts for it on stdin without
+/// echoing it.
+fn read_passphrase(var: &str, prompt: &str) -> Option<String> {
+    if let Ok(passphrase) = env::var(var) {
+        return Some(passphrase);
+    }
+    prompt_passphrase(prompt)
+}
+
+/// Like `read_passphrase`, but for a passphrase that's about to be set, which is prompted for
+/// twice so a typo doesn't lock the documents away.
+fn read_new_passphrase(var: &str, prompt: &str) -> Option<String> {
+    let passphrase = match env::var(var) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let passphrase = prompt_passphrase(prompt)?;
+            if prompt_passphrase("Repeat it")? != passphrase {
+                eprintln!("error: the passphrases don't match");
+                return None;
+            }
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        eprintln!("error: the passphrase can't be empty");
+        return None;
+    }
+    Some(passphrase)
+}
+
+fn prompt_passphrase(prompt: &str) -> Option<String> {
+    eprint!("{}: ", prompt);
+    io::stderr().flush().ok()?;
+    match read_hidden_line() {
+        Ok(line) => Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()),
+        Err(e) => {
+            eprintln!("error: could not read the passphrase: {}", e);
+            None
+        }
+    }
+}
+
+/// Reads a line from stdin, turning off the terminal's echo meanwhile if it's one.
+#[cfg(unix)]
+fn read_hidden_line() -> io::Result<String> {
+    let fd = libc::STDIN_FILENO;
+    // SAFETY: `termios` is plain data, which `tcgetattr` fills in if stdin is a terminal.
+    let mut echoing: libc::termios = unsafe { std::mem::zeroed() };
+    let terminal = unsafe { libc::tcgetattr(fd, &mut echoing) } == 0;
+    if terminal {
+        let mut hidden = echoing;
+        hidden.c_lflag &= !libc::ECHO;
+        hidden.c_lflag |= libc::ECHONL;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &hidden) };
+    }
+    let mut line = String::new();
+    let result = io::stdin().lock().read_line(&mut line);
+    if terminal {
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &echoing) };
+    }
+    result.map(|_| line)
+}
+
+#[cfg(not(unix))]
+fn read_hidden_line() -> io::Result<String> {
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line)
+}
+
+/// Encrypts or decrypts the documents named in `m`.
+fn encrypt(m: &ArgMatches, encrypt: bool) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let selected: Vec<Document> = match m
+        .values_of_os("file")
+        .unwrap()
+        .map(|name| find_doc(&docs, name))
+        .collect()
+    {
+        Some(selected) => selected,
+        None => return EXIT_FAILURE,
+    };
+    let key = match unlock(&cabinet) {
+        Some(key) => key,
+        None => return EXIT_FAILURE,
+    };
+    let mut code = EXIT_OK;
+    for mut doc in selected {
+        let old_path = doc.path.clone();
+        let result = if encrypt {
+            cabinet.encrypt(&mut doc, &key)
+        } else {
+            cabinet.decrypt(&mut doc, &key)
+        };
+        match result {
+            Ok(new_path) => println!("{} -> {}", old_path.display(), new_path.display()),
+            Err(e) => {
+                let action = if encrypt { "encrypt" } else { "decrypt" };
+                eprintln!(
+                    "error: could not {} \"{}\": {}",
+                    action,
+                    old_path.display(),
+                    e
+                );
+                code = EXIT_FAILURE;
+            }
+        }
+    }
+    code
+}
+
+/// Reads the passphrase and unwraps the cabinet's key. The key is never created on the way, as
+/// `key init` makes sure its passphrase was typed right.
+fn unlock(cabinet: &Cabinet) -> Option<Key> {
+    if !cabinet.has_key() {
+        eprintln!("error: the cabinet has no key yet, create one with `key init`");
+        return None;
+    }
+    let passphrase = read_passphrase(PASSPHRASE_VAR, "Passphrase")?;
+    match cabinet.unlock(&passphrase) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            eprintln!("error: could not unlock the cabinet: {}", e);
+            None
+        }
+    }
+}
+
+fn key_init(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let passphrase = match read_new_passphrase(PASSPHRASE_VAR, "Passphrase") {
+        Some(passphrase) => passphrase,
+        None => return EXIT_FAILURE,
+    };
+    match cabinet.init_key(&passphrase) {
+        Ok(_) => EXIT_OK,
+        Err(e) => {
+            eprintln!("error: could not create the key: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn key_passwd(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let passphrases = read_passphrase(PASSPHRASE_VAR, "Passphrase").and_then(|old| {
+        Some((
+            old,
+            read_new_passphrase(NEW_PASSPHRASE_VAR, "New passphrase")?,
+        ))
+    });
+    let (old, new) = match passphrases {
+        Some(passphrases) => passphrases,
+        None => return EXIT_FAILURE,
+    };
+    match cabinet.change_passphrase(&old, &new) {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("error: could not change the passphrase: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn key_rotate(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let passphrase = match read_passphrase(PASSPHRASE_VAR, "Passphrase") {
+        Some(passphrase) => passphrase,
+        None => return EXIT_FAILURE,
+    };
+    let encrypted = match read_docs(cabinet.encrypted_documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let progress = ProgressBar::new(encrypted.len() as u64);
+    progress.set_style(ProgressStyle::default_bar().template("{bar:40} {pos}/{len} {wide_msg}"));
+    let result = cabinet.rotate_key(&passphrase, |doc| {
+        progress.set_message(&doc.relative_path());
+        progress.inc(1);
+    });
+    progress.finish_and_clear();
+    match result {
+        Ok(failed) if failed.is_empty() => EXIT_OK,
+        Ok(failed) => {
+            for (doc, e) in failed {
+                eprintln!(
+                    "error: could not re-encrypt \"{}\": {}",
+                    doc.path.display(),
+                    e
+                );
+            }
+            eprintln!("error: the rotation is incomplete, run it again to resume");
+            EXIT_FAILURE
+        }
+        Err(e) => {
+            eprintln!("error: could not rotate the key: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn export(m: &ArgMatches) -> i32 {
+    let format = match m.value_of("format") {
+        Some("csv") => Format::Csv,
+        _ => Format::JsonLines,
+    };
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let result = match m.value_of("output") {
+        Some(output) => File::create(output).and_then(|f| export::write(&docs, format, f)),
+        None => export::write(&docs, format, io::stdout()),
+    };
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("error: could not export: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn config(m: &ArgMatches) -> i32 {
+    let mut cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    if [
+        "template",
+        "field",
+        "recursive",
+        "layout",
+        "inbox",
+        "purge-after",
+        "shred-passes",
+    ]
+    .iter()
+    .any(|name| m.is_present(name))
+    {
+        let mut config = cabinet.config().clone();
+        match m.value_of("purge-after") {
+            Some("") => config.purge_after_days = None,
+            Some(days) => match days.parse() {
+                Ok(days) => config.purge_after_days = Some(days),
+                Err(_) => {
+                    eprintln!("error: invalid number of days \"{}\"", days);
+                    return EXIT_USAGE;
+                }
+            },
+            None => (),
+        }
+        match m.value_of("shred-passes").map(str::parse) {
+            Some(Ok(passes)) => config.shred_passes = Some(passes),
+            Some(Err(_)) => {
+                eprintln!("error: invalid number of passes");
+                return EXIT_USAGE;
+            }
+            None => (),
+        }
+        if let Some(inbox) = m.value_of("inbox") {
+            config.inbox = Some(inbox.to_string()).filter(|inbox| !inbox.is_empty());
+        }
+        if let Some(fields) = m.values_of("field") {
+            config.fields = fields.map(|f| f.to_string()).collect();
+        }
+        if let Some(recursive) = m.value_of("recursive") {
+            config.recursive = recursive == "true";
+        }
+        let result = match m.value_of("template") {
+            Some(template) => Template::parse(template).map(|t| config.template = t),
+            None => Ok(()),
+        }
+        .and_then(|_| match m.value_of("layout") {
+            Some("") => {
+                config.layout = None;
+                Ok(())
+            }
+            Some(layout) => Layout::parse(layout).map(|l| config.layout = Some(l)),
+            None => Ok(()),
+        })
+        .and_then(|_| cabinet.set_config(config));
+        if let Err(e) = result {
+            eprintln!("error: {}", e);
+            return EXIT_FAILURE;
+        }
+    }
+    println!(
+        "{}",
+        serde_json::to_string_pretty(cabinet.config()).unwrap()
+    );
+    EXIT_OK
+}
+
+fn manifest(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let manifest = match cabinet.manifest() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("error: could not read the manifest: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let selected: Vec<Document> = match m.values_of_os("files") {
+        Some(names) => match names.map(|name| find_doc(&docs, name)).collect() {
+            Some(selected) => selected,
+            None => return EXIT_FAILURE,
+        },
+        None => docs
+            .into_iter()
+            .filter(|doc| {
+                !manifest
+                    .entries
+                    .contains_key(&cabinet.relative_path(&doc.path))
+            })
+            .collect(),
+    };
+    match cabinet.record(&selected) {
+        Ok(()) => {
+            for doc in &selected {
+                println!("recorded {}", doc.relative_path());
+            }
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("error: could not update the manifest: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn file(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let layout = match &cabinet.config().layout {
+        Some(layout) => layout,
+        None => {
+            eprintln!("error: the cabinet has no filing layout, set one with `config --layout`");
+            return EXIT_FAILURE;
+        }
+    };
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let selected: Vec<Document> = match m.values_of_os("files") {
+        Some(names) => match names.map(|name| find_doc(&docs, name)).collect() {
+            Some(selected) => selected,
+            None => return EXIT_FAILURE,
+        },
+        None => docs.into_iter().filter(|doc| doc.is_normalized()).collect(),
+    };
+    let mut code = EXIT_OK;
+    for doc in selected {
+        if m.is_present("dry-run") {
+            match layout.folder(&doc) {
+                Ok(folder) if folder != doc.folder => {
+                    println!("{} -> {}/", doc.relative_path(), folder)
+                }
+                Ok(_) => {}
+                Err(field) => {
+                    eprintln!("skipped \"{}\": missing {}", doc.relative_path(), field);
+                    code = EXIT_FAILURE;
+                }
+            }
+            continue;
+        }
+        // Filing an earlier document may have moved this one along as one of its pages.
+        if !Path::new(&doc.path).exists() {
+            continue;
+        }
+        match cabinet.file(&doc, conflict_policy(m)) {
+            Ok(moved) => {
+                for (old, new) in moved {
+                    println!("{} -> {}", old.display(), new.display());
+                }
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                code = EXIT_FAILURE;
+            }
+        }
+    }
+    code
+}
+
+fn archive(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    if cabinet.inbox().is_none() {
+        eprintln!("error: the cabinet has no inbox, set one with `config --inbox`");
+        return EXIT_FAILURE;
+    }
+    let template = cabinet.template();
+    let docs = match read_docs(cabinet.inbox_documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let selected: Vec<Document> = match m.values_of_os("files") {
+        Some(names) => match names.map(|name| find_doc(&docs, name)).collect() {
+            Some(selected) => selected,
+            None => return EXIT_FAILURE,
+        },
+        None => docs,
+    };
+    let mut code = EXIT_OK;
+    for mut doc in selected {
+        // Like `normalize`, only documents whose filename holds every field but the page.
+        let missing: Vec<&str> = OptDoc::new(&doc.path, template)
+            .missing(template)
+            .into_iter()
+            .filter(|&field| field != "page")
+            .collect();
+        if !missing.is_empty() {
+            eprintln!(
+                "skipped \"{}\": missing {}",
+                doc.relative_path(),
+                missing.join(", ")
+            );
+            code = EXIT_FAILURE;
+        } else if m.is_present("dry-run") {
+            println!(
+                "{} -> {}",
+                doc.relative_path(),
+                doc.normalized_filename(template)
+            );
+        } else {
+            let old_path = doc.path.clone();
+            match cabinet.archive(&mut doc, conflict_policy(m)) {
+                Ok(new_path) => println!("{} -> {}", old_path.display(), new_path.display()),
+                Err(e) => {
+                    eprintln!("error: could not archive \"{}\": {}", old_path.display(), e);
+                    code = EXIT_FAILURE;
+                }
+            }
+        }
+    }
+    code
+}
+
+fn history(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let journal = match cabinet.journal() {
+        Ok(journal) => journal,
+        Err(e) => {
+            eprintln!("error: could not read the journal: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    for operation in &journal.operations {
+        println!("{}  {}", operation.time, operation);
+    }
+    // The most recently undone operation is the oldest of them.
+    for operation in journal.undone.iter().rev() {
+        println!("{}  {} (undone)", operation.time, operation);
+    }
+    EXIT_OK
+}
+
+/// Undoes the last operations of the journal, or redoes the last undone ones.
+fn undo(m: &ArgMatches, redo: bool) -> i32 {
+    let count: usize = match m.value_of("count").unwrap().parse() {
+        Ok(count) => count,
+        Err(_) => {
+            eprintln!("error: expected a number of operations");
+            return EXIT_USAGE;
+        }
+    };
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    let verb = if redo { "redo" } else { "undo" };
+    let mut key = None;
+    for _ in 0..count {
+        let journal = match cabinet.journal() {
+            Ok(journal) => journal,
+            Err(e) => {
+                eprintln!("error: could not read the journal: {}", e);
+                return EXIT_FAILURE;
+            }
+        };
+        let next = if redo {
+            journal.undone.last()
+        } else {
+            journal.operations.last()
+        };
+        let next = match next {
+            Some(next) => next,
+            None => {
+                println!("nothing to {}", verb);
+                break;
+            }
+        };
+        // Only ask for the passphrase once it's needed.
+        if next.action.needs_key() && key.is_none() {
+            key = match unlock(&cabinet) {
+                Some(key) => Some(key),
+                None => return EXIT_FAILURE,
+            };
+        }
+        let result = if redo {
+            cabinet.redo(key.as_ref())
+        } else {
+            cabinet.undo(key.as_ref())
+        };
+        match result {
+            Ok(Some(operation)) => println!("{}: {}", verb, operation),
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return EXIT_FAILURE;
+            }
+        }
+    }
+    EXIT_OK
+}
+
+fn verify(m: &ArgMatches) -> i32 {
+    let cabinet = match open(m) {
+        Some(cabinet) => cabinet,
+        None => return EXIT_FAILURE,
+    };
+    if m.is_present("integrity") {
+        return verify_integrity(&cabinet);
+    }
+    let docs = match read_docs(cabinet.documents()) {
+        Some(docs) => docs,
+        None => return EXIT_FAILURE,
+    };
+    let unnormalized: Vec<Document> = docs
+        .into_iter()
+        .filter(|doc| !doc.is_normalized())
+        .collect();
+    for doc in &unnormalized {
+        println!("unnormalized: {}", doc.relative_path());
+    }
+    if unnormalized.is_empty() {
+        EXIT_OK
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+fn verify_integrity(cabinet: &Cabinet) -> i32 {
+    let report = match cabinet.verify_integrity() {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("error: could not verify: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    let show = |name: &String| utils::path_from_string(name).display().to_string();
+    for name in &report.missing {
+        println!("missing: {}", show(name));
+    }
+    for name in &report.modified {
+        println!("modified: {}", show(name));
+    }
+    for name in &report.unknown {
+        println!("unknown: {}", show(name));
+    }
+    if report.is_ok() {
+        EXIT_OK
+    } else {
+        EXIT_FAILURE
+    }
+}
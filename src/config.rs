@@ -0,0 +1,136 @@
+use crate::errors::{ErrorKind, Result};
+use crate::layout::{Layout, DATE_FIELDS};
+use crate::template::{Template, FIELDS};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory inside the cabinet that holds its configuration.
+pub const CONFIG_DIR: &str = ".filecabinet";
+const CONFIG_FILE: &str = "config.json";
+
+/// Names that custom fields can't use because exports already have a column for them.
+const RESERVED: [&str; 7] = [
+    "path",
+    "folder",
+    "filename",
+    "extension",
+    "encrypted",
+    "size",
+    "normalized",
+];
+
+/// Per-cabinet settings, stored in `.filecabinet/config.json` in the cabinet's directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub template: Template,
+    /// Custom fields, such as `owner` or `account`, that the template can use besides the
+    /// built-in ones.
+    pub fields: Vec<String>,
+    /// Whether documents in subfolders are part of the cabinet.
+    pub recursive: bool,
+    /// Folders that normalized documents are filed into, e.g. `{year}/{institution}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Layout>,
+    /// Directory that new documents arrive in, e.g. a scanner's drop folder, relative to the
+    /// cabinet or absolute. Documents are archived into the cabinet once they're edited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inbox: Option<String>,
+    /// Days after which deleted documents are purged from the trash.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge_after_days: Option<u32>,
+    /// Times shredded documents are overwritten with random data before they're deleted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shred_passes: Option<u32>,
+}
+
+impl Config {
+    pub fn path<P: AsRef<Path>>(root: P) -> PathBuf {
+        root.as_ref().join(CONFIG_DIR).join(CONFIG_FILE)
+    }
+
+    /// Loads the config of the cabinet at `root`, or the default config if it has none.
+    pub fn load<P: AsRef<Path>>(root: P) -> Result<Config> {
+        let path = Self::path(root);
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        let config: Config = serde_json::from_str(&contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that the template only uses known fields and that every custom field is part of
+    /// the template, since a document's fields are only stored in its filename.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: String| Err(ErrorKind::InvalidConfig(reason).into());
+        for name in &self.fields {
+            if FIELDS.contains(&name.as_str()) || RESERVED.contains(&name.as_str()) {
+                return invalid(format!("\"{}\" can't be used as a custom field", name));
+            }
+            if !self.template.has_field(name) {
+                return invalid(format!("field \"{}\" is not used in the template", name));
+            }
+        }
+        for field in self.template.custom_fields() {
+            if !self.fields.contains(&field.name) {
+                return invalid(format!("template uses unknown field \"{}\"", field.name));
+            }
+        }
+        for name in self.layout.iter().flat_map(Layout::fields) {
+            if !DATE_FIELDS.contains(&name) && !self.template.has_field(name) {
+                return invalid(format!("layout uses unknown field \"{}\"", name));
+            }
+        }
+        if self.purge_after_days == Some(0) {
+            return invalid("purge_after_days must be at least 1".to_string());
+        }
+        if self.shred_passes == Some(0) {
+            return invalid("shred_passes must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether documents in subfolders are part of the cabinet. Filed documents always are.
+    pub fn scans_subfolders(&self) -> bool {
+        self.recursive || self.layout.is_some()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, root: P) -> Result<()> {
+        self.validate()?;
+        let path = Self::path(root);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_config_format() {
+    let config: Config =
+        serde_json::from_str(r#"{"template": "{institution}-{date}-{title}"}"#).unwrap();
+    assert_eq!(config.template.separator(), "-");
+    assert_eq!(
+        serde_json::from_str::<Config>("{}").unwrap(),
+        Config::default()
+    );
+    assert!(serde_json::from_str::<Config>(r#"{"template": "{date}{title}"}"#).is_err());
+}
+
+#[test]
+fn test_validate_custom_fields() {
+    let config = |template: &str, fields: &[&str]| Config {
+        template: Template::parse(template).unwrap(),
+        fields: fields.iter().map(|f| f.to_string()).collect(),
+        ..Default::default()
+    };
+    let template = "{date}_{owner}_{institution}_{account?}_{title}_{page}";
+    assert!(config(template, &["owner", "account"]).validate().is_ok());
+    assert!(config(template, &["owner"]).validate().is_err());
+    assert!(config("{date}_{title}", &["owner"]).validate().is_err());
+    assert!(config("{date}_{size}", &["size"]).validate().is_err());
+}
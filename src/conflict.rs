@@ -0,0 +1,82 @@
+use crate::errors::{ErrorKind, Result};
+use crate::{atomic, Document, Template};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What to do when a document would be renamed to a file that already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Leave both files alone and fail.
+    #[default]
+    Cancel,
+    /// Bump the document's page number until its name is free.
+    Increment,
+    /// Replace the existing file, but only if its contents are identical to the document's.
+    Overwrite,
+}
+
+/// Applies `policy` to a document about to be renamed into `dir` under its normalized name,
+/// bumping its page if needed. Returns the existing file it should replace, if any.
+pub fn resolve(
+    doc: &mut Document,
+    template: &Template,
+    dir: &Path,
+    policy: ConflictPolicy,
+) -> Result<Option<PathBuf>> {
+    loop {
+        let target = dir.join(doc.normalized_filename(template));
+        if !target.exists() || target == doc.path {
+            return Ok(None);
+        }
+        match policy {
+            ConflictPolicy::Increment if template.has_field("page") => {
+                let page = doc.page.parse::<u32>().unwrap_or(1) + 1;
+                doc.page = format!("{:0width$}", page, width = doc.page.len());
+            }
+            ConflictPolicy::Overwrite if identical(&doc.path, &target)? => {
+                return Ok(Some(target));
+            }
+            ConflictPolicy::Overwrite => {
+                bail!(ErrorKind::NotIdentical(target.display().to_string()))
+            }
+            _ => bail!(ErrorKind::Exists(target.display().to_string())),
+        }
+    }
+}
+
+/// Whether two files have the same contents.
+pub fn identical<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> io::Result<bool> {
+    if fs::metadata(&a)?.len() != fs::metadata(&b)?.len() {
+        return Ok(false);
+    }
+    Ok(atomic::checksum(&fs::read(a)?) == atomic::checksum(&fs::read(b)?))
+}
+
+#[test]
+fn test_resolve() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let template = Template::default();
+    fs::write(dir.join("2020-01-01_Bank_Statement_1.pdf"), "page 1").unwrap();
+    fs::write(dir.join("2020-01-01_Bank_Statement_2.pdf"), "page 2").unwrap();
+    let scan = dir.join("scan.pdf");
+    fs::write(&scan, "page 1").unwrap();
+    let mut doc = Document::new(scan.to_str().unwrap().to_string(), &template).unwrap();
+    doc.date = "2020-01-01".to_string();
+    doc.institution = "Bank".to_string();
+    doc.title = "Statement".to_string();
+
+    assert!(resolve(&mut doc, &template, dir, ConflictPolicy::Cancel).is_err());
+    assert_eq!(
+        resolve(&mut doc, &template, dir, ConflictPolicy::Overwrite).unwrap(),
+        Some(dir.join("2020-01-01_Bank_Statement_1.pdf"))
+    );
+    assert_eq!(
+        resolve(&mut doc, &template, dir, ConflictPolicy::Increment).unwrap(),
+        None
+    );
+    assert_eq!(doc.page, "3");
+    doc.page = "2".to_string();
+    assert!(resolve(&mut doc, &template, dir, ConflictPolicy::Overwrite).is_err());
+}
@@ -0,0 +1,197 @@
+use crate::atomic;
+use crate::errors::{Error, ErrorKind, Result};
+use cocoon::Cocoon;
+use data_encoding::HEXLOWER;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extension appended to the filename of encrypted documents, e.g. `statement.pdf.cocoon`.
+pub const ENCRYPTED_EXTENSION: &str = "cocoon";
+
+impl From<cocoon::Error> for Error {
+    fn from(error: cocoon::Error) -> Error {
+        match error {
+            cocoon::Error::Io(e) => e.into(),
+            cocoon::Error::Cryptography => {
+                ErrorKind::Crypto("wrong passphrase or corrupted file".to_string()).into()
+            }
+            cocoon::Error::UnrecognizedFormat => {
+                ErrorKind::Crypto("not an encrypted file".to_string()).into()
+            }
+            cocoon::Error::TooLarge => ErrorKind::Crypto("file is too large".to_string()).into(),
+            cocoon::Error::TooShort => ErrorKind::Crypto("file is truncated".to_string()).into(),
+        }
+    }
+}
+
+/// The random key a cabinet's documents are encrypted with. It's stored in the cabinet's
+/// keyfile wrapped with the passphrase, so the passphrase can change without re-encrypting
+/// every document.
+#[derive(Clone, PartialEq)]
+pub struct Key(String);
+
+impl Key {
+    pub fn generate() -> Result<Key> {
+        let mut bytes = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| ErrorKind::Crypto("could not generate a key".to_string()))?;
+        Ok(Key(HEXLOWER.encode(&bytes)))
+    }
+
+    /// Encrypts the key with `passphrase` for storing it in a keyfile.
+    pub fn wrap(&self, passphrase: &str) -> Result<Vec<u8>> {
+        if passphrase.is_empty() {
+            bail!(ErrorKind::Crypto("the passphrase is empty".to_string()));
+        }
+        encrypt(self.0.as_bytes(), passphrase)
+    }
+
+    /// Decrypts a key wrapped by `wrap`.
+    pub fn unwrap(data: &[u8], passphrase: &str) -> Result<Key> {
+        let key = String::from_utf8(decrypt(data, passphrase)?)
+            .map_err(|_| ErrorKind::Crypto("the keyfile is corrupted".to_string()))?;
+        Ok(Key(key))
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// Whether `path` is an encrypted document.
+pub fn is_encrypted<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(ENCRYPTED_EXTENSION))
+}
+
+/// The path of an encrypted document without its `.cocoon` extension.
+pub fn decrypted_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    if is_encrypted(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// The path an encrypted copy of `path` is stored at.
+pub fn encrypted_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_os_string();
+    name.push(".");
+    name.push(ENCRYPTED_EXTENSION);
+    PathBuf::from(name)
+}
+
+pub fn encrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    Ok(Cocoon::new(passphrase.as_bytes()).wrap(data)?)
+}
+
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    Ok(Cocoon::parse_only(passphrase.as_bytes()).unwrap(data)?)
+}
+
+/// Encrypts the file at `path` to `<path>.cocoon` and removes the original.
+/// Returns the path of the encrypted file.
+pub fn encrypt_file<P: AsRef<Path>>(path: P, key: &Key) -> Result<PathBuf> {
+    let path = path.as_ref();
+    if is_encrypted(path) {
+        bail!(ErrorKind::Crypto(format!(
+            "\"{}\" is already encrypted",
+            path.display()
+        )));
+    }
+    let target = encrypted_path(path);
+    replace(path, &target, encrypt(&fs::read(path)?, &key.0)?)?;
+    Ok(target)
+}
+
+/// Decrypts the `.cocoon` file at `path` next to it and removes the encrypted file.
+/// Returns the path of the decrypted file.
+pub fn decrypt_file<P: AsRef<Path>>(path: P, key: &Key) -> Result<PathBuf> {
+    let path = path.as_ref();
+    if !is_encrypted(path) {
+        bail!(ErrorKind::Crypto(format!(
+            "\"{}\" is not encrypted",
+            path.display()
+        )));
+    }
+    let target = decrypted_path(path);
+    replace(path, &target, decrypt(&fs::read(path)?, &key.0)?)?;
+    Ok(target)
+}
+
+/// Reads the file at `path`, decrypting it in memory if it's encrypted so that no plaintext
+/// is written to disk.
+pub fn read_file<P: AsRef<Path>>(path: P, key: &Key) -> Result<Vec<u8>> {
+    let data = fs::read(&path)?;
+    if is_encrypted(&path) {
+        decrypt(&data, &key.0)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Re-encrypts the `.cocoon` file at `path` from `old` to `new` in place. Files that are
+/// already encrypted with `new`, e.g. by an interrupted rotation, are left as they are.
+pub fn rekey_file<P: AsRef<Path>>(path: P, old: &Key, new: &Key) -> Result<()> {
+    let path = path.as_ref();
+    let data = fs::read(path)?;
+    let plaintext = match decrypt(&data, &old.0) {
+        Ok(plaintext) => plaintext,
+        Err(e) => match decrypt(&data, &new.0) {
+            Ok(_) => return Ok(()),
+            Err(_) => return Err(e),
+        },
+    };
+    atomic::replace(path, &encrypt(&plaintext, &new.0)?)?;
+    Ok(())
+}
+
+/// Writes `contents` to `target` and removes `source`, refusing to overwrite `target`.
+/// `source` is only removed once `target` is completely written, so an interrupted call
+/// never loses the document.
+fn replace(source: &Path, target: &Path, contents: Vec<u8>) -> Result<()> {
+    if target.exists() {
+        bail!(ErrorKind::Exists(target.display().to_string()));
+    }
+    atomic::write(target, &contents)?;
+    fs::remove_file(source)?;
+    Ok(())
+}
+
+#[test]
+fn test_encrypted_paths() {
+    let path = Path::new("/a/2020-01-01_Bank_Statement_1.pdf");
+    let encrypted = encrypted_path(path);
+    assert_eq!(
+        encrypted,
+        Path::new("/a/2020-01-01_Bank_Statement_1.pdf.cocoon")
+    );
+    assert!(is_encrypted(&encrypted));
+    assert!(!is_encrypted(path));
+    assert_eq!(decrypted_path(&encrypted), path);
+}
+
+#[test]
+fn test_wrap_key() {
+    let key = Key::generate().unwrap();
+    let wrapped = key.wrap("secret").unwrap();
+    assert_eq!(Key::unwrap(&wrapped, "secret").unwrap(), key);
+    assert!(Key::unwrap(&wrapped, "wrong").is_err());
+    assert!(key.wrap("").is_err());
+}
+
+#[test]
+fn test_encrypt_roundtrip() {
+    let data = b"statement".to_vec();
+    let encrypted = encrypt(&data, "secret").unwrap();
+    assert_ne!(encrypted, data);
+    assert_eq!(decrypt(&encrypted, "secret").unwrap(), data);
+    assert!(decrypt(&encrypted, "wrong").is_err());
+}
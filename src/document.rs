@@ -0,0 +1,325 @@
+use crate::atomic;
+use crate::crypto;
+use crate::errors::{ErrorKind, Result};
+use crate::template::Template;
+use crate::utils::{self, OptDoc};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file in the cabinet along with the fields parsed from its filename.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    /// Path of the file, which may not be valid UTF-8. `display_path` shows it.
+    pub path: PathBuf,
+    /// Folder of the document relative to the cabinet, e.g. `2021/Chase`, or empty if it's at
+    /// the cabinet's top level.
+    #[serde(default)]
+    pub folder: String,
+    /// The file's name for display, with characters that aren't valid UTF-8 replaced.
+    pub filename: String,
+    pub date: String,
+    pub institution: String,
+    pub title: String,
+    pub page: String,
+    /// Values of the custom fields used by the cabinet's template.
+    pub fields: BTreeMap<String, String>,
+    pub extension: String,
+    pub encrypted: bool,
+    normalized: bool,
+}
+
+impl Document {
+    /// Parses the document at `path` according to `template`. Fields that can't be parsed from
+    /// the filename fall back to today's date, empty strings and page 1. Fails if the path has
+    /// no file name.
+    pub fn new<P: Into<PathBuf>>(path: P, template: &Template) -> Result<Self> {
+        let path = path.into();
+        let options = OptDoc::new(&path, template);
+        let normalized = utils::is_normalized(&path, template);
+        let default_page = if template.is_required("page") {
+            "1"
+        } else {
+            ""
+        };
+        let now: DateTime<Utc> = Utc::now();
+        let encrypted = crypto::is_encrypted(&path);
+        let plain_path = crypto::decrypted_path(&path);
+        let file_stem = match plain_path.file_stem() {
+            Some(file_stem) => file_stem.to_string_lossy(),
+            None => {
+                let reason = "it has no file name".to_string();
+                return Err(ErrorKind::InvalidPath(path.display().to_string(), reason).into());
+            }
+        };
+        let extension = utils::extension(&plain_path);
+        let filename = if encrypted {
+            format!(
+                "{}.{}.{}",
+                file_stem,
+                extension,
+                crypto::ENCRYPTED_EXTENSION
+            )
+        } else {
+            format!("{}.{}", file_stem, extension)
+        };
+        let fields = template
+            .custom_fields()
+            .map(|f| {
+                let value = options.fields.get(&f.name).cloned().unwrap_or_default();
+                (f.name.clone(), value)
+            })
+            .collect();
+        Ok(Document {
+            path,
+            folder: String::new(),
+            filename,
+            date: options
+                .date
+                .unwrap_or_else(|| now.format("%Y-%m-%d").to_string()),
+            institution: options.institution.unwrap_or_default(),
+            title: options.name.unwrap_or_default(),
+            page: options.page.unwrap_or_else(|| String::from(default_page)),
+            fields,
+            extension,
+            encrypted,
+            normalized,
+        })
+    }
+
+    /// The document's path for display, with characters that aren't valid UTF-8 replaced.
+    pub fn display_path(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    /// The document's path relative to the cabinet.
+    pub fn relative_path(&self) -> String {
+        let name = Path::new(&self.path)
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        if self.folder.is_empty() {
+            name.into_owned()
+        } else {
+            format!("{}/{}", self.folder, name)
+        }
+    }
+
+    /// Whether the document is in `folder` or one of its subfolders. Every document is in the
+    /// empty folder.
+    pub fn is_in(&self, folder: &str) -> bool {
+        let folder = folder.trim_matches('/');
+        folder.is_empty()
+            || self.folder == folder
+            || self
+                .folder
+                .strip_prefix(folder)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        match name {
+            "date" => Some(&self.date),
+            "institution" => Some(&self.institution),
+            "title" => Some(&self.title),
+            "page" => Some(&self.page),
+            custom => self.fields.get(custom).map(String::as_str),
+        }
+    }
+
+    /// The year of the document's date, e.g. `2021`.
+    pub fn year(&self) -> Option<&str> {
+        self.date.get(..4)
+    }
+
+    /// The month of the document's date, e.g. `03`.
+    pub fn month(&self) -> Option<&str> {
+        self.date.get(5..7)
+    }
+
+    /// Sets a built-in or custom field.
+    pub fn set_field(&mut self, name: &str, value: String) {
+        match name {
+            "date" => self.date = value,
+            "institution" => self.institution = value,
+            "title" => self.title = value,
+            "page" => self.page = value,
+            custom => {
+                self.fields.insert(custom.to_string(), value);
+            }
+        }
+    }
+
+    /// The filename this document should have according to its fields.
+    pub fn normalized_filename(&self, template: &Template) -> String {
+        let institution = utils::to_camelcase(&self.institution);
+        let title = utils::to_camelcase(&self.title);
+        let fields: BTreeMap<&str, String> = self
+            .fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), utils::to_camelcase(value)))
+            .collect();
+        let stem = template.render(|name| match name {
+            "institution" => Some(&institution),
+            "title" => Some(&title),
+            "date" | "page" => self.field(name),
+            custom => fields.get(custom).map(String::as_str),
+        });
+        if self.encrypted {
+            format!(
+                "{}.{}.{}",
+                stem,
+                &self.extension,
+                crypto::ENCRYPTED_EXTENSION
+            )
+        } else {
+            format!("{}.{}", stem, &self.extension)
+        }
+    }
+
+    /// Whether the file's name on disk matches the template.
+    pub fn is_normalized(&self) -> bool {
+        self.normalized
+    }
+
+    /// Normalizes the document's fields and renames the file on disk to match them. Fails if
+    /// another file already has the new name. Returns the new path.
+    pub fn rename(&mut self, template: &Template) -> io::Result<PathBuf> {
+        let dir = self
+            .path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        self.move_to(template, dir)
+    }
+
+    /// Like `rename`, but moves the file into `dir`.
+    pub fn move_to<P: AsRef<Path>>(&mut self, template: &Template, dir: P) -> io::Result<PathBuf> {
+        self.normalize_fields();
+        let filename = self.normalized_filename(template);
+        let new_path = dir.as_ref().join(&filename);
+        if new_path != self.path {
+            atomic::move_file(&self.path, &new_path)?;
+        }
+        self.normalized = utils::is_normalized(&new_path, template);
+        self.path = new_path.clone();
+        self.filename = filename;
+        Ok(new_path)
+    }
+
+    /// Camel-cases the fields the way normalized filenames spell them.
+    pub fn normalize_fields(&mut self) {
+        self.institution = utils::to_camelcase(&self.institution);
+        self.title = utils::to_camelcase(&self.title);
+        for value in self.fields.values_mut() {
+            *value = utils::to_camelcase(value);
+        }
+    }
+
+    /// Removes the document's file from disk.
+    pub fn delete(&self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
+
+#[test]
+fn test_normalized_filename() {
+    let template = Template::default();
+    let mut doc = Document::new(
+        "/tmp/20200103_first bank_statement.PDF".to_string(),
+        &template,
+    )
+    .unwrap();
+    assert_eq!(
+        doc.normalized_filename(&template),
+        "2020-01-03_FirstBank_Statement_1.pdf"
+    );
+    doc.page = "2".to_string();
+    assert_eq!(
+        doc.normalized_filename(&template),
+        "2020-01-03_FirstBank_Statement_2.pdf"
+    );
+    let template = Template::parse("{institution}-{date}-{title}-{page?}").unwrap();
+    let doc = Document::new("/tmp/Bank-2020-01-03-Statement.pdf".to_string(), &template).unwrap();
+    assert!(doc.is_normalized());
+    assert_eq!(
+        doc.normalized_filename(&template),
+        "Bank-2020-01-03-Statement.pdf"
+    );
+    assert!(Document::new("/".to_string(), &template).is_err());
+}
+
+#[test]
+fn test_custom_fields() {
+    let template = Template::parse("{date}_{owner}_{institution}_{title}_{page}").unwrap();
+    let mut doc = Document::new(
+        "/tmp/2020-01-03_Alex_Bank_Statement_1.pdf".to_string(),
+        &template,
+    )
+    .unwrap();
+    assert!(doc.is_normalized());
+    assert_eq!(doc.field("owner"), Some("Alex"));
+    doc.set_field("owner", "sam lee".to_string());
+    assert_eq!(
+        doc.normalized_filename(&template),
+        "2020-01-03_SamLee_Bank_Statement_1.pdf"
+    );
+}
+
+#[test]
+fn test_encrypted_document() {
+    let template = Template::default();
+    let doc = Document::new(
+        "/tmp/2020-01-03_Bank_Statement_1.pdf.cocoon".to_string(),
+        &template,
+    )
+    .unwrap();
+    assert!(doc.encrypted);
+    assert!(doc.is_normalized());
+    assert_eq!(doc.extension, "pdf");
+    assert_eq!(doc.page, "1");
+    assert_eq!(doc.normalized_filename(&template), doc.filename);
+}
+
+#[test]
+fn test_is_in() {
+    let mut doc = Document::new(
+        "/tmp/2021/Chase/2021-01-01_Chase_Statement_1.pdf".to_string(),
+        &Template::default(),
+    )
+    .unwrap();
+    doc.folder = "2021/Chase".to_string();
+    assert_eq!(
+        doc.relative_path(),
+        "2021/Chase/2021-01-01_Chase_Statement_1.pdf"
+    );
+    assert!(doc.is_in(""));
+    assert!(doc.is_in("2021"));
+    assert!(doc.is_in("2021/Chase/"));
+    assert!(!doc.is_in("2021/Cha"));
+    assert!(!doc.is_in("2020"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_filename() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let path = dir.join(OsStr::from_bytes(b"Rechnung_M\xfcller.pdf"));
+    std::fs::write(&path, "a").unwrap();
+    let template = Template::default();
+    let mut doc = Document::new(&path, &template).unwrap();
+    assert_eq!(doc.filename, "Rechnung_M\u{fffd}ller.pdf");
+    doc.date = "2019-05-01".to_string();
+    doc.institution = "mueller".to_string();
+    doc.title = "invoice".to_string();
+    let new_path = doc.rename(&template).unwrap();
+    assert_eq!(new_path, dir.join("2019-05-01_Mueller_Invoice_1.pdf"));
+    assert!(new_path.exists() && !path.exists());
+}
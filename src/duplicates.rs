@@ -0,0 +1,77 @@
+use crate::{atomic, Document};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Groups documents whose files are byte-identical, whatever their names. Only files that
+/// share their size with another one are hashed, in parallel. Files that can't be read are
+/// left out. Groups are sorted by path.
+pub fn find(docs: Vec<Document>) -> Vec<Vec<Document>> {
+    let mut by_size: BTreeMap<u64, Vec<Document>> = BTreeMap::new();
+    for doc in docs {
+        if let Ok(metadata) = fs::metadata(&doc.path) {
+            by_size.entry(metadata.len()).or_default().push(doc);
+        }
+    }
+    let candidates: Vec<Document> = by_size
+        .into_values()
+        .filter(|docs| docs.len() > 1)
+        .flatten()
+        .collect();
+    let hashed: Vec<(String, Document)> = candidates
+        .into_par_iter()
+        .filter_map(|doc| {
+            let data = fs::read(&doc.path).ok()?;
+            Some((atomic::checksum(&data), doc))
+        })
+        .collect();
+    let mut by_hash: BTreeMap<String, Vec<Document>> = BTreeMap::new();
+    for (hash, doc) in hashed {
+        by_hash.entry(hash).or_default().push(doc);
+    }
+    let mut groups: Vec<Vec<Document>> = by_hash
+        .into_values()
+        .map(|mut docs| {
+            docs.sort_by(|a, b| a.path.cmp(&b.path));
+            docs
+        })
+        .filter(|docs| docs.len() > 1)
+        .collect();
+    groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+    groups
+}
+
+/// The index of the copy to keep by default: the first one with a normalized filename, or
+/// else the first one.
+pub fn preferred(group: &[Document]) -> usize {
+    group
+        .iter()
+        .position(|doc| doc.is_normalized())
+        .unwrap_or(0)
+}
+
+#[test]
+fn test_find() {
+    use crate::Template;
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let template = Template::default();
+    let docs: Vec<Document> = [
+        ("scan001.pdf", "letter"),
+        ("2020-01-01_Bank_Letter_1.pdf", "letter"),
+        ("scan002.pdf", "statement"),
+        ("scan003.pdf", "lettex"),
+    ]
+    .iter()
+    .map(|(name, contents)| {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        Document::new(path.to_str().unwrap().to_string(), &template).unwrap()
+    })
+    .collect();
+    let groups = find(docs);
+    assert_eq!(groups.len(), 1);
+    let names: Vec<&str> = groups[0].iter().map(|doc| doc.filename.as_str()).collect();
+    assert_eq!(names, vec!["2020-01-01_Bank_Letter_1.pdf", "scan001.pdf"]);
+    assert_eq!(preferred(&groups[0]), 0);
+}
@@ -0,0 +1,56 @@
+//! Errors returned by the library.
+#![allow(unexpected_cfgs)]
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+        Json(serde_json::Error);
+    }
+
+    errors {
+        InvalidTemplate(template: String, reason: String) {
+            description("invalid filename template")
+            display("invalid filename template \"{}\": {}", template, reason)
+        }
+        InvalidLayout(layout: String, reason: String) {
+            description("invalid filing layout")
+            display("invalid filing layout \"{}\": {}", layout, reason)
+        }
+        InvalidConfig(reason: String) {
+            description("invalid cabinet config")
+            display("invalid cabinet config: {}", reason)
+        }
+        Crypto(reason: String) {
+            description("encryption error")
+            display("encryption error: {}", reason)
+        }
+        CantFile(path: String, reason: String) {
+            description("document can't be filed")
+            display("can't file \"{}\": {}", path, reason)
+        }
+        Unreadable(path: String, reason: String) {
+            description("file or folder can't be read")
+            display("can't read \"{}\": {}", path, reason)
+        }
+        InvalidPath(path: String, reason: String) {
+            description("invalid document path")
+            display("invalid path \"{}\": {}", path, reason)
+        }
+        Exists(path: String) {
+            description("file already exists")
+            display("\"{}\" already exists", path)
+        }
+        NotIdentical(path: String) {
+            description("file already exists with different contents")
+            display("\"{}\" already exists with different contents", path)
+        }
+        Locked {
+            description("cabinet is in use by another process")
+            display("the cabinet is in use by another process, try again once it's done")
+        }
+        History(reason: String) {
+            description("operation can't be undone or redone")
+            display("{}", reason)
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::Document;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Filenames the catalog is written to by `write_catalog`.
+pub const CATALOG_JSONL: &str = "filecabinet-catalog.jsonl";
+pub const CATALOG_CSV: &str = "filecabinet-catalog.csv";
+
+const CSV_HEADER: [&str; 10] = [
+    "path",
+    "folder",
+    "date",
+    "institution",
+    "title",
+    "page",
+    "extension",
+    "encrypted",
+    "size",
+    "normalized",
+];
+
+/// One row of the catalog. Custom fields follow the built-in columns.
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    pub path: String,
+    pub folder: String,
+    pub date: String,
+    pub institution: String,
+    pub title: String,
+    pub page: String,
+    pub extension: String,
+    pub encrypted: bool,
+    pub size: u64,
+    pub normalized: bool,
+    #[serde(flatten)]
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Record {
+    pub fn new(doc: &Document) -> io::Result<Record> {
+        Ok(Record {
+            path: doc.display_path(),
+            folder: doc.folder.clone(),
+            date: doc.date.clone(),
+            institution: doc.institution.clone(),
+            title: doc.title.clone(),
+            page: doc.page.clone(),
+            extension: doc.extension.clone(),
+            encrypted: doc.encrypted,
+            size: fs::metadata(&doc.path)?.len(),
+            normalized: doc.is_normalized(),
+            fields: doc.fields.clone(),
+        })
+    }
+
+    fn csv_fields(&self, custom_fields: &BTreeSet<&str>) -> Vec<String> {
+        let mut fields = vec![
+            self.path.clone(),
+            self.folder.clone(),
+            self.date.clone(),
+            self.institution.clone(),
+            self.title.clone(),
+            self.page.clone(),
+            self.extension.clone(),
+            self.encrypted.to_string(),
+            self.size.to_string(),
+            self.normalized.to_string(),
+        ];
+        for name in custom_fields {
+            fields.push(self.fields.get(*name).cloned().unwrap_or_default());
+        }
+        fields
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    JsonLines,
+    Csv,
+}
+
+/// Writes one record per document to `writer`.
+pub fn write<W: Write>(docs: &[Document], format: Format, writer: W) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    let custom_fields: BTreeSet<&str> = docs
+        .iter()
+        .flat_map(|doc| doc.fields.keys().map(String::as_str))
+        .collect();
+    if format == Format::Csv {
+        let header: Vec<&str> = CSV_HEADER.iter().chain(&custom_fields).copied().collect();
+        write_csv_row(&mut writer, &header)?;
+    }
+    for doc in docs {
+        let record = Record::new(doc)?;
+        match format {
+            Format::JsonLines => {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+            Format::Csv => write_csv_row(&mut writer, &record.csv_fields(&custom_fields))?,
+        }
+    }
+    writer.flush()
+}
+
+/// Writes the catalog of `docs` to `dir` in both formats and returns the written paths.
+pub fn write_catalog<P: AsRef<Path>>(docs: &[Document], dir: P) -> io::Result<Vec<PathBuf>> {
+    let jsonl = dir.as_ref().join(CATALOG_JSONL);
+    let csv = dir.as_ref().join(CATALOG_CSV);
+    write(docs, Format::JsonLines, File::create(&jsonl)?)?;
+    write(docs, Format::Csv, File::create(&csv)?)?;
+    Ok(vec![jsonl, csv])
+}
+
+fn write_csv_row<W: Write, S: AsRef<str>>(writer: &mut W, fields: &[S]) -> io::Result<()> {
+    let row: Vec<String> = fields.iter().map(|f| csv_escape(f.as_ref())).collect();
+    write!(writer, "{}\r\n", row.join(","))
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[test]
+fn test_csv_escape() {
+    assert_eq!(csv_escape("Statement"), "Statement");
+    assert_eq!(csv_escape("a,b"), "\"a,b\"");
+    assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+}
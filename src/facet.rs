@@ -0,0 +1,131 @@
+use crate::filter::Matcher;
+use crate::Document;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A property of documents to narrow them down by, such as their institution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Facet {
+    Institution,
+    Year,
+    Extension,
+    Encrypted,
+}
+
+impl Facet {
+    pub const ALL: [Facet; 4] = [
+        Facet::Institution,
+        Facet::Year,
+        Facet::Extension,
+        Facet::Encrypted,
+    ];
+
+    /// The document's value for the facet, which is empty if it has none.
+    pub fn value(self, doc: &Document) -> String {
+        match self {
+            Facet::Institution => doc.institution.clone(),
+            Facet::Year => doc.year().unwrap_or_default().to_string(),
+            Facet::Extension => doc.extension.to_lowercase(),
+            Facet::Encrypted if doc.encrypted => "encrypted".to_string(),
+            Facet::Encrypted => "not encrypted".to_string(),
+        }
+    }
+
+    /// The distinct values of the facet among `docs`, in order, with how many documents have
+    /// each.
+    pub fn counts<'a, I>(self, docs: I) -> Vec<(String, usize)>
+    where
+        I: IntoIterator<Item = &'a Document>,
+    {
+        let mut counts = BTreeMap::new();
+        for doc in docs {
+            *counts.entry(self.value(doc)).or_insert(0) += 1;
+        }
+        counts.into_iter().collect()
+    }
+}
+
+impl fmt::Display for Facet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Facet::Institution => "institution",
+            Facet::Year => "year",
+            Facet::Extension => "extension",
+            Facet::Encrypted => "encryption",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The value picked for some of the facets. Documents match if they have the picked value of
+/// every facet that has one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Facets {
+    selected: BTreeMap<Facet, String>,
+}
+
+impl Facets {
+    /// Whether no facet has a picked value, so that every document matches.
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
+    pub fn get(&self, facet: Facet) -> Option<&str> {
+        self.selected.get(&facet).map(String::as_str)
+    }
+
+    /// Picks a value for the facet, or lets it have any value if `value` is `None`.
+    pub fn set(&mut self, facet: Facet, value: Option<String>) {
+        match value {
+            Some(value) => self.selected.insert(facet, value),
+            None => self.selected.remove(&facet),
+        };
+    }
+}
+
+impl Matcher for Facets {
+    fn matches(&self, doc: &Document) -> bool {
+        self.selected
+            .iter()
+            .all(|(facet, value)| facet.value(doc) == *value)
+    }
+}
+
+#[test]
+fn test_facets() {
+    use crate::{Filter, Template};
+
+    let template = Template::default();
+    let docs: Vec<Document> = [
+        "/tmp/2020-01-01_Chase_Statement_1.pdf",
+        "/tmp/2021-01-01_Chase_Statement_1.pdf.cocoon",
+        "/tmp/2021-02-01_Amex_Statement_1.JPG",
+        "/tmp/scan.jpg",
+    ]
+    .iter()
+    .map(|path| Document::new(*path, &template).unwrap())
+    .collect();
+    assert_eq!(
+        Facet::Institution.counts(&docs),
+        vec![
+            (String::new(), 1),
+            ("Amex".to_string(), 1),
+            ("Chase".to_string(), 2)
+        ]
+    );
+    assert_eq!(
+        Facet::Extension.counts(&docs),
+        vec![("jpg".to_string(), 2), ("pdf".to_string(), 2)]
+    );
+    assert_eq!(Facet::Encrypted.value(&docs[1]), "encrypted");
+
+    let mut facets = Facets::default();
+    facets.set(Facet::Institution, Some("Chase".to_string()));
+    facets.set(Facet::Year, Some("2021".to_string()));
+    let matching = |matcher: &dyn Matcher| docs.iter().filter(|doc| matcher.matches(doc)).count();
+    assert_eq!(matching(&facets), 1);
+    facets.set(Facet::Year, None);
+    assert_eq!(matching(&facets), 2);
+    assert_eq!(matching(&Filter::Unnormalized.and(&facets)), 0);
+    assert_eq!(matching(&Filter::Normalized.and(&facets)), 2);
+}
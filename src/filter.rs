@@ -0,0 +1,49 @@
+use crate::Document;
+use serde::{Deserialize, Serialize};
+
+/// A way of narrowing down documents, which can be combined with others using `and`.
+pub trait Matcher {
+    fn matches(&self, doc: &Document) -> bool;
+
+    /// Matches the documents matched by both `self` and `other`.
+    fn and<M: Matcher>(self, other: M) -> And<Self, M>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn matches(&self, doc: &Document) -> bool {
+        (**self).matches(doc)
+    }
+}
+
+/// The documents matched by two matchers, made by `Matcher::and`.
+#[derive(Debug, Clone, Copy)]
+pub struct And<A, B>(pub A, pub B);
+
+impl<A: Matcher, B: Matcher> Matcher for And<A, B> {
+    fn matches(&self, doc: &Document) -> bool {
+        self.0.matches(doc) && self.1.matches(doc)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Filter {
+    #[default]
+    All,
+    Normalized,
+    Unnormalized,
+}
+
+impl Matcher for Filter {
+    fn matches(&self, doc: &Document) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::Normalized => doc.is_normalized(),
+            Filter::Unnormalized => !doc.is_normalized(),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::Document;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What documents can be listed under headers by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    #[default]
+    Nothing,
+    Institution,
+    Year,
+    /// Years, with the months of each year under them.
+    Month,
+}
+
+impl GroupBy {
+    pub const ALL: [GroupBy; 4] = [
+        GroupBy::Nothing,
+        GroupBy::Institution,
+        GroupBy::Year,
+        GroupBy::Month,
+    ];
+
+    /// The values of the headers the document is listed under, from the outermost, e.g.
+    /// `["2021", "2021-03"]` by month. They're empty for documents that have no such value.
+    pub fn values(self, doc: &Document) -> Vec<String> {
+        let year = doc.year().unwrap_or_default().to_string();
+        match self {
+            GroupBy::Nothing => Vec::new(),
+            GroupBy::Institution => vec![doc.institution.clone()],
+            GroupBy::Year => vec![year],
+            GroupBy::Month => {
+                let month = doc.month().map(|month| format!("{}-{}", year, month));
+                vec![year, month.unwrap_or_default()]
+            }
+        }
+    }
+
+    /// Groups `docs`, in the order of each group's first document. Documents keep their order
+    /// within a group.
+    pub fn group(self, docs: &[&Document]) -> Vec<Group> {
+        let values: Vec<Vec<String>> = docs.iter().map(|doc| self.values(doc)).collect();
+        let all: Vec<usize> = (0..docs.len()).collect();
+        nest(&values, &all, 0)
+    }
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            GroupBy::Nothing => "nothing",
+            GroupBy::Institution => "institution",
+            GroupBy::Year => "year",
+            GroupBy::Month => "year and month",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Documents listed under the same header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    /// The value the documents have in common, e.g. `2021`.
+    pub value: String,
+    /// Indices of the documents, including those of the subgroups.
+    pub docs: Vec<usize>,
+    /// Groups within the group, e.g. the months of a year.
+    pub subgroups: Vec<Group>,
+}
+
+/// Groups the documents at `indices` by their value at `depth`.
+fn nest(values: &[Vec<String>], indices: &[usize], depth: usize) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+    for &i in indices {
+        let value = match values[i].get(depth) {
+            Some(value) => value,
+            None => continue,
+        };
+        match groups.iter_mut().find(|group| &group.value == value) {
+            Some(group) => group.docs.push(i),
+            None => groups.push(Group {
+                value: value.clone(),
+                docs: vec![i],
+                subgroups: Vec::new(),
+            }),
+        }
+    }
+    for group in &mut groups {
+        group.subgroups = nest(values, &group.docs, depth + 1);
+    }
+    groups
+}
+
+#[test]
+fn test_group() {
+    let template = crate::Template::default();
+    let docs: Vec<Document> = [
+        "/tmp/2021-03-01_Chase_Statement_1.pdf",
+        "/tmp/2020-12-01_Amex_Statement_1.pdf",
+        "/tmp/2021-04-01_Chase_Statement_1.pdf",
+        "/tmp/2021-03-15_Amex_Statement_1.pdf",
+    ]
+    .iter()
+    .map(|path| Document::new(*path, &template).unwrap())
+    .collect();
+    let docs: Vec<&Document> = docs.iter().collect();
+    assert!(GroupBy::Nothing.group(&docs).is_empty());
+    let groups = GroupBy::Institution.group(&docs);
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].value, "Chase");
+    assert_eq!(groups[0].docs, vec![0, 2]);
+    assert!(groups[0].subgroups.is_empty());
+    let groups = GroupBy::Month.group(&docs);
+    let values = |groups: &[Group]| -> Vec<String> {
+        groups.iter().map(|group| group.value.clone()).collect()
+    };
+    assert_eq!(values(&groups), ["2021", "2020"]);
+    assert_eq!(groups[0].docs, vec![0, 2, 3]);
+    assert_eq!(values(&groups[0].subgroups), ["2021-03", "2021-04"]);
+    assert_eq!(groups[0].subgroups[0].docs, vec![0, 3]);
+}
@@ -0,0 +1,183 @@
+use crate::atomic;
+use crate::config::CONFIG_DIR;
+use crate::errors::Result;
+use crate::utils;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const JOURNAL_FILE: &str = "journal.json";
+
+/// What an operation did to a document's file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Rename,
+    /// A rename into another folder, when filing or archiving.
+    Move,
+    /// A permanent deletion.
+    Delete,
+    /// A move into the trash.
+    Trash,
+    /// A move out of the trash.
+    Restore,
+    /// A deletion after overwriting the file with random data.
+    Shred,
+    Encrypt,
+    Decrypt,
+}
+
+impl Action {
+    /// Whether replaying the action needs the cabinet's key.
+    pub fn needs_key(self) -> bool {
+        self == Action::Encrypt || self == Action::Decrypt
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Action::Rename => "rename",
+            Action::Move => "move",
+            Action::Delete => "delete",
+            Action::Trash => "trash",
+            Action::Restore => "restore",
+            Action::Shred => "shred",
+            Action::Encrypt => "encrypt",
+            Action::Decrypt => "decrypt",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A file at a point of the journal, by its path relative to the cabinet and its digest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    /// As `utils::path_to_string` gives it.
+    pub path: String,
+    pub sha256: String,
+}
+
+impl FileState {
+    /// The current state of the file at `path`, under or relative to `root`.
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(root: P, path: Q) -> io::Result<FileState> {
+        let (root, path) = (root.as_ref(), path.as_ref());
+        Ok(FileState {
+            path: utils::path_to_string(path.strip_prefix(root).unwrap_or(path)),
+            sha256: atomic::checksum(&fs::read(root.join(path))?),
+        })
+    }
+
+    /// Where the file is under `root`.
+    pub fn file<P: AsRef<Path>>(&self, root: P) -> PathBuf {
+        root.as_ref().join(utils::path_from_string(&self.path))
+    }
+}
+
+/// An operation on a document, with the file before and after it, if there's one after.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Operation {
+    pub action: Action,
+    /// When the operation was done, in RFC 3339.
+    pub time: String,
+    pub before: FileState,
+    pub after: Option<FileState>,
+}
+
+impl Operation {
+    pub fn new(action: Action, before: FileState, after: Option<FileState>) -> Operation {
+        Operation {
+            action,
+            time: Utc::now().to_rfc3339(),
+            before,
+            after,
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = |state: &FileState| utils::path_from_string(&state.path);
+        write!(f, "{} {}", self.action, path(&self.before).display())?;
+        if let Some(after) = &self.after {
+            write!(f, " -> {}", path(after).display())?;
+        }
+        Ok(())
+    }
+}
+
+/// The operations done on a cabinet's documents, stored in `.filecabinet/journal.json` so they
+/// can be undone and redone across sessions.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Journal {
+    /// Operations in the order they were done.
+    pub operations: Vec<Operation>,
+    /// Operations undone since the last new one, the most recently undone last.
+    pub undone: Vec<Operation>,
+}
+
+impl Journal {
+    pub fn path<P: AsRef<Path>>(root: P) -> PathBuf {
+        root.as_ref().join(CONFIG_DIR).join(JOURNAL_FILE)
+    }
+
+    /// Loads the journal of the cabinet at `root`, or an empty one if it has none.
+    pub fn load<P: AsRef<Path>>(root: P) -> Result<Journal> {
+        let path = Self::path(root);
+        if !path.exists() {
+            return Ok(Journal::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, root: P) -> Result<()> {
+        let path = Self::path(root);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        atomic::replace(path, serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Adds a new operation, which can't be followed by the ones undone before it.
+    pub fn record(&mut self, operation: Operation) {
+        self.operations.push(operation);
+        self.undone.clear();
+    }
+
+    /// Points every state recorded with the digest `old` at `new`, after the file was
+    /// rewritten with the same meaning, e.g. encrypted again.
+    pub fn replace_digest(&mut self, old: &str, new: &str) {
+        let operations = self.operations.iter_mut().chain(self.undone.iter_mut());
+        for operation in operations {
+            let states = std::iter::once(&mut operation.before).chain(operation.after.as_mut());
+            for state in states.filter(|state| state.sha256 == old) {
+                state.sha256 = new.to_string();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_record() {
+    let state = |path: &str| FileState {
+        path: path.to_string(),
+        sha256: String::new(),
+    };
+    let mut journal = Journal::default();
+    journal
+        .undone
+        .push(Operation::new(Action::Delete, state("a.pdf"), None));
+    let rename = Operation::new(Action::Rename, state("scan.pdf"), Some(state("b.pdf")));
+    assert_eq!(rename.to_string(), "rename scan.pdf -> b.pdf");
+    journal.record(rename);
+    assert_eq!(journal.operations.len(), 1);
+    assert!(journal.undone.is_empty());
+    let json = serde_json::to_string(&journal).unwrap();
+    assert!(json.contains(r#""action":"rename""#));
+    assert_eq!(serde_json::from_str::<Journal>(&json).unwrap(), journal);
+}
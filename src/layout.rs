@@ -0,0 +1,133 @@
+use crate::errors::{Error, ErrorKind, Result};
+use crate::Document;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Fields a layout can use besides the template's: parts of the document's date.
+pub const DATE_FIELDS: [&str; 2] = ["year", "month"];
+
+lazy_static! {
+    static ref RE_PLACEHOLDER: Regex = Regex::new(r"\{(?P<name>\w+)\}").unwrap();
+}
+
+/// A folder hierarchy such as `{year}/{institution}` that normalized documents are filed
+/// into, relative to the cabinet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Layout {
+    segments: Vec<String>,
+}
+
+impl Layout {
+    pub fn parse(spec: &str) -> Result<Layout> {
+        let invalid = |reason: &str| -> Error {
+            ErrorKind::InvalidLayout(spec.to_string(), reason.to_string()).into()
+        };
+        let segments: Vec<String> = spec
+            .trim_matches('/')
+            .split('/')
+            .map(str::to_string)
+            .collect();
+        for segment in &segments {
+            if segment.is_empty() {
+                return Err(invalid("empty folder name"));
+            }
+            // Hidden folders aren't scanned, so documents filed there would disappear.
+            if segment.starts_with('.') {
+                return Err(invalid("folders can't start with a dot"));
+            }
+            if RE_PLACEHOLDER
+                .replace_all(segment, "")
+                .contains(&['{', '}'][..])
+            {
+                return Err(invalid("unbalanced braces"));
+            }
+        }
+        Ok(Layout { segments })
+    }
+
+    /// The fields the layout refers to.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().flat_map(|segment| {
+            RE_PLACEHOLDER
+                .captures_iter(segment)
+                .map(|captures| captures.get(1).unwrap().as_str())
+        })
+    }
+
+    /// The folder `doc` belongs in, or the name of the first field it has no value for.
+    pub fn folder(&self, doc: &Document) -> std::result::Result<String, String> {
+        let mut missing = None;
+        let segments: Vec<String> = self
+            .segments
+            .iter()
+            .map(|segment| {
+                RE_PLACEHOLDER
+                    .replace_all(segment, |captures: &regex::Captures| {
+                        let name = &captures["name"];
+                        let value = match name {
+                            "year" => doc.year(),
+                            "month" => doc.month(),
+                            name => doc.field(name),
+                        };
+                        match value {
+                            Some(value) if !value.is_empty() => value.to_string(),
+                            _ => {
+                                missing.get_or_insert_with(|| name.to_string());
+                                String::new()
+                            }
+                        }
+                    })
+                    .into_owned()
+            })
+            .collect();
+        match missing {
+            Some(name) => Err(name),
+            None => Ok(segments.join("/")),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.segments.join("/"))
+    }
+}
+
+impl TryFrom<String> for Layout {
+    type Error = Error;
+
+    fn try_from(spec: String) -> Result<Layout> {
+        Layout::parse(&spec)
+    }
+}
+
+impl From<Layout> for String {
+    fn from(layout: Layout) -> String {
+        layout.to_string()
+    }
+}
+
+#[test]
+fn test_layout_folder() {
+    use crate::Template;
+    let layout = Layout::parse("{year}/{institution}/").unwrap();
+    assert_eq!(layout.to_string(), "{year}/{institution}");
+    assert_eq!(
+        layout.fields().collect::<Vec<_>>(),
+        vec!["year", "institution"]
+    );
+    let mut doc = Document::new(
+        "/tmp/2021-03-04_Chase_Statement_1.pdf".to_string(),
+        &Template::default(),
+    )
+    .unwrap();
+    assert_eq!(layout.folder(&doc), Ok("2021/Chase".to_string()));
+    doc.institution = String::new();
+    assert_eq!(layout.folder(&doc), Err("institution".to_string()));
+    assert!(Layout::parse("{year}//{institution}").is_err());
+    assert!(Layout::parse(".archive/{year}").is_err());
+    assert!(Layout::parse("{year").is_err());
+}
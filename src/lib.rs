@@ -0,0 +1,44 @@
+//! The filecabinet document model.
+//!
+//! Documents in a cabinet are named after a template, `{date}_{institution}_{title}_{page}.{ext}`
+//! by default. This crate parses those names, checks whether a file is normalized and renames
+//! files to match their fields, without depending on any user interface.
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate lazy_static;
+
+pub mod activity;
+pub mod atomic;
+pub mod cabinet;
+pub mod config;
+pub mod conflict;
+pub mod crypto;
+pub mod document;
+pub mod duplicates;
+pub mod errors;
+pub mod export;
+pub mod facet;
+pub mod filter;
+pub mod group;
+pub mod journal;
+pub mod layout;
+pub mod manifest;
+pub mod search;
+pub mod shred;
+pub mod sort;
+pub mod template;
+pub mod trash;
+pub mod utils;
+
+pub use cabinet::Cabinet;
+pub use config::Config;
+pub use conflict::ConflictPolicy;
+pub use document::Document;
+pub use errors::{Error, ErrorKind, Result};
+pub use facet::{Facet, Facets};
+pub use filter::{Filter, Matcher};
+pub use group::{Group, GroupBy};
+pub use search::Query;
+pub use sort::{SortKey, SortOrder};
+pub use template::Template;
@@ -1,40 +1,81 @@
-#[macro_use]
-extern crate lazy_static;
-use crate::utils::OptDoc;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Local};
+use filecabinet::activity::{self, ActivityLog, Event as ActivityEvent};
+use filecabinet::crypto::{self, Key};
+use filecabinet::trash::Entry as TrashEntry;
+use filecabinet::{
+    export, utils, Cabinet, ConflictPolicy, Document, ErrorKind, Facet, Facets, Filter, Group,
+    GroupBy, Matcher, Query, SortKey, SortOrder,
+};
 use iced::futures::{AsyncReadExt, AsyncWriteExt};
 use iced::widget::pane_grid::Pane;
 use iced::{
-    button, pane_grid, scrollable, text_input, Align, Application, Button, Checkbox, Column,
-    Command, Container, Element, Font, HorizontalAlignment, Image, Length, PaneGrid, Row,
-    Scrollable, Settings, Text, TextInput,
+    button, image, pane_grid, pick_list, scrollable, text_input, Align, Application, Button,
+    Checkbox, Column, Command, Container, Element, Font, HorizontalAlignment, Image, Length,
+    PaneGrid, PickList, Row, Scrollable, Settings, Space, Subscription, Text, TextInput,
 };
+use iced_native::keyboard::{self, KeyCode};
+use iced_native::{event, Event};
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
-use std::fs;
-use std::path::Path;
-mod utils;
-
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug};
+use std::path::{Path, PathBuf};
+mod cli;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Number of errors the status area shows at once.
+const STATUS_LINES: usize = 5;
+/// How many of the most recent events the activity pane shows.
+const ACTIVITY_LINES: usize = 200;
+/// The activity pane's choice to show events of every kind.
+const ALL_KINDS: &str = "All events";
 
 pub fn main() -> iced::Result {
+    let matches = cli::app(VERSION).get_matches();
+    if matches.subcommand_name().is_some() {
+        std::process::exit(cli::run(&matches));
+    }
     println!("VERSION: {}", VERSION);
     FileCabinet::run(Settings::default())
 }
 
 enum FileCabinet {
     Loading,
-    Loaded(State),
+    Loaded(Box<State>),
 }
 
 struct State {
     refresh_state: button::State,
+    export_state: button::State,
+    duplicates_state: button::State,
+    trash_state: button::State,
+    activity_state: button::State,
+    undo_state: button::State,
+    redo_state: button::State,
+    status_state: button::State,
     target_dir_state: text_input::State,
     target_dir: String,
+    passphrase_state: text_input::State,
+    passphrase: String,
+    /// The passphrase again, to create the cabinet's key.
+    repeated_passphrase_state: text_input::State,
+    repeated_passphrase: String,
+    create_key_state: button::State,
+    /// Whether the cabinet has a key yet, as it's only created on request.
+    has_key: bool,
+    /// The cabinet's key, unlocked with the passphrase the first time it's needed.
+    key: Option<Key>,
     panes: pane_grid::State<Box<dyn PaneContent>>,
     doc_pane: Option<Pane>,
+    inbox_pane: Option<Pane>,
     preview_pane: Option<Pane>,
-    preview_image: String,
+    preview_image: PathBuf,
+    sort: SortOrder,
+    group_by: GroupBy,
+    duplicates_pane: Option<Pane>,
+    trash_pane: Option<Pane>,
+    activity_pane: Option<Pane>,
+    /// Errors shown at the bottom of the window until they're dismissed, oldest first.
+    status: Vec<String>,
     dirty: bool,
     saving: bool,
 }
@@ -45,58 +86,570 @@ impl Default for State {
             pane_grid::State::new(Box::new(DocPane::default()) as Box<dyn PaneContent>);
         State {
             refresh_state: Default::default(),
+            export_state: Default::default(),
+            duplicates_state: Default::default(),
+            trash_state: Default::default(),
+            activity_state: Default::default(),
+            undo_state: Default::default(),
+            redo_state: Default::default(),
+            status_state: Default::default(),
             target_dir_state: Default::default(),
             target_dir: "".to_string(),
+            passphrase_state: Default::default(),
+            passphrase: "".to_string(),
+            repeated_passphrase_state: Default::default(),
+            repeated_passphrase: "".to_string(),
+            create_key_state: Default::default(),
+            has_key: false,
+            key: None,
             panes: pane_state,
             doc_pane: Some(pane),
+            inbox_pane: None,
             preview_pane: None,
-            preview_image: "".to_string(),
+            preview_image: PathBuf::new(),
+            sort: SortOrder::default(),
+            group_by: GroupBy::default(),
+            duplicates_pane: None,
+            trash_pane: None,
+            activity_pane: None,
+            status: Vec::new(),
             dirty: false,
             saving: false,
         }
     }
 }
 
+impl State {
+    /// Checks whether the cabinet has a key yet, and opens a pane listing its inbox next to the
+    /// documents if it has one, or closes it if it no longer does.
+    fn sync_cabinet(&mut self) {
+        let cabinet = Cabinet::open(&self.target_dir).ok();
+        self.has_key = cabinet.as_ref().is_some_and(Cabinet::has_key);
+        let has_inbox = cabinet.is_some_and(|c| c.inbox().is_some());
+        match (has_inbox, self.inbox_pane) {
+            (true, None) => {
+                if let Some(doc_pane) = &self.doc_pane {
+                    let mut inbox = DocPane {
+                        inbox: true,
+                        key: self.key.clone(),
+                        sort: self.sort,
+                        group_by: self.group_by,
+                        ..Default::default()
+                    };
+                    inbox.open(&self.target_dir);
+                    self.inbox_pane = self
+                        .panes
+                        .split(pane_grid::Axis::Vertical, doc_pane, Box::new(inbox))
+                        .map(|(pane, _)| pane);
+                }
+            }
+            (false, Some(pane)) => {
+                self.panes.close(&pane);
+                self.inbox_pane = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// The cabinet's key, unlocking it with the session's passphrase if it isn't yet. The panes
+    /// are given the key, so it's only derived from the passphrase once, which is slow.
+    fn key(&mut self) -> Option<Key> {
+        if self.key.is_none() {
+            if !self.has_key {
+                self.report(
+                    "The cabinet has no key yet, repeat the passphrase to create one".to_string(),
+                );
+                return None;
+            }
+            let passphrase = &self.passphrase;
+            match Cabinet::open(&self.target_dir).and_then(|cabinet| cabinet.unlock(passphrase)) {
+                Ok(key) => self.set_key(key),
+                Err(e) => {
+                    log(ActivityEvent::new("UnlockFailed").detail(&e));
+                    self.report(format!("Could not unlock the key: {}", e));
+                }
+            }
+        }
+        self.key.clone()
+    }
+
+    /// Creates the cabinet's key, wrapped with the passphrase once it's been typed twice the
+    /// same, since a typo would make every document encrypted with it unrecoverable.
+    fn create_key(&mut self) {
+        if self.passphrase.is_empty() || self.passphrase != self.repeated_passphrase {
+            self.report("The passphrases are empty or don't match".to_string());
+            return;
+        }
+        let passphrase = &self.passphrase;
+        match Cabinet::open(&self.target_dir).and_then(|cabinet| cabinet.init_key(passphrase)) {
+            Ok(key) => {
+                log(ActivityEvent::new("CreateKey").path(&self.target_dir));
+                self.has_key = true;
+                self.repeated_passphrase.clear();
+                self.set_key(key);
+            }
+            Err(e) => {
+                log(ActivityEvent::new("CreateKeyFailed").detail(&e));
+                self.report(format!("Could not create the key: {}", e));
+            }
+        }
+    }
+
+    fn set_key(&mut self, key: Key) {
+        for (_pane, boxed_content) in self.panes.iter_mut() {
+            boxed_content.update(Message::KeyUnlocked(key.clone()));
+        }
+        self.key = Some(key);
+    }
+
+    /// Removes the temporary files interrupted writes left in the cabinet. It's done when the
+    /// cabinet is opened on launch or refreshed, rather than on every edit of its path.
+    fn remove_temp_files(&mut self) {
+        let removed = match Cabinet::open(&self.target_dir) {
+            Ok(cabinet) => cabinet.remove_temp_files(),
+            // The panes report it.
+            Err(_) => return,
+        };
+        match removed {
+            Ok(removed) => {
+                for path in removed {
+                    log(ActivityEvent::new("RemoveTemp").path(path));
+                }
+            }
+            Err(e) => {
+                log(ActivityEvent::new("RemoveTempFailed").path(&self.target_dir).detail(&e));
+                self.report(format!("Could not remove temporary files: {}", e));
+            }
+        }
+    }
+
+    /// Shows an error in the status area, unless it's already the last one shown.
+    fn report(&mut self, error: String) {
+        if self.status.last() != Some(&error) {
+            self.status.push(error);
+        }
+    }
+
+    /// Undoes the last operation on the cabinet's documents, or redoes the last undone one.
+    fn undo(&mut self, redo: bool) {
+        let cabinet = match Cabinet::open(&self.target_dir) {
+            Ok(cabinet) => cabinet,
+            Err(e) => {
+                log(ActivityEvent::new("OpenFailed").path(&self.target_dir).detail(&e));
+                self.report(format!("Could not open \"{}\": {}", self.target_dir, e));
+                return;
+            }
+        };
+        let journal = match cabinet.journal() {
+            Ok(journal) => journal,
+            Err(e) => {
+                log(ActivityEvent::new("JournalFailed").detail(&e));
+                self.report(format!("Could not read the history: {}", e));
+                return;
+            }
+        };
+        let next = if redo {
+            journal.undone.last()
+        } else {
+            journal.operations.last()
+        };
+        // Only unlock the key for encryption, it's slow.
+        let key = match next {
+            Some(operation) if operation.action.needs_key() => match self.key() {
+                Some(key) => Some(key),
+                None => return,
+            },
+            _ => None,
+        };
+        let (event, result) = if redo {
+            ("Redo", cabinet.redo(key.as_ref()))
+        } else {
+            ("Undo", cabinet.undo(key.as_ref()))
+        };
+        match result {
+            Ok(Some(operation)) => log(ActivityEvent::new(event).detail(operation)),
+            Ok(None) => {}
+            Err(e) => {
+                log(ActivityEvent::new(&format!("{}Failed", event)).detail(&e));
+                self.report(e.to_string());
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 enum Message {
     RefreshTargetDir(String),
+    Export,
     Loaded(Result<SavedState, LoadError>),
     Saved(Result<(), SaveError>),
     PathChanged(String),
+    PassphraseChanged(String),
+    RepeatedPassphraseChanged(String),
+    CreateKey,
+    KeyUnlocked(Key),
     FilterChanged(Filter),
-    DocMessage(usize, DocMessage),
+    FolderChanged(String),
+    SearchChanged(String),
+    FacetSelected(FacetChoice),
+    SortChanged(SortOrder),
+    GroupByChanged(GroupBy),
+    ToggleGroup(Pane, String),
+    RecursiveToggled(bool),
+    EncryptSelected(bool),
+    FileSelected,
+    DocMessage(Pane, usize, DocMessage),
     ClosePreviewPane(Pane),
+    FindDuplicates,
+    KeepDuplicate(usize, usize),
+    CloseDuplicatesPane(Pane),
+    OpenTrash,
+    RestoreTrashed(usize),
+    EmptyTrash,
+    CloseTrashPane(Pane),
+    OpenActivity,
+    ActivityKindSelected(String),
+    RefreshActivity,
+    CloseActivityPane(Pane),
+    DismissStatus,
+    Undo,
+    Redo,
     Dragged(pane_grid::DragEvent),
     Resized(pane_grid::ResizeEvent),
 }
 
 #[derive(Debug, Default)]
 struct DocPane {
+    /// Whether the pane lists the documents waiting in the cabinet's inbox. Finishing an edit
+    /// there archives the document instead of renaming it in place.
+    inbox: bool,
     scroll: scrollable::State,
     filter: Filter,
+    /// Only documents in this folder or its subfolders are listed.
+    folder: String,
+    folder_input: text_input::State,
+    /// The text of the search box, and the search it's parsed into.
+    search: String,
+    query: Query,
+    facets: Facets,
+    sort: SortOrder,
+    group_by: GroupBy,
+    /// The collapsed headers, by their values and those of the headers above them, joined
+    /// with `/`.
+    collapsed: HashSet<String>,
+    header_buttons: HashMap<String, button::State>,
     controls: Controls,
-    docs: Vec<Document>,
+    cabinet: Option<Cabinet>,
+    /// The session's key, once it's unlocked.
+    key: Option<Key>,
+    docs: Vec<DocItem>,
+    errors: Vec<String>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct PreviewPane {
     preview_image_path: String,
+    preview: Preview,
+    close_button: button::State,
+    scroll_state: scrollable::State,
+}
+
+#[derive(Debug)]
+enum Preview {
+    /// The image, or the first pages of a PDF.
+    Images(Vec<image::Handle>),
+    Unavailable(String),
+}
+
+/// Width in pixels PDF pages are rendered at.
+const PDF_PREVIEW_WIDTH: i32 = 800;
+/// PDF previews stop after this many pages, which is plenty to recognize a document.
+const PDF_PREVIEW_PAGES: usize = 10;
+
+impl PreviewPane {
+    /// Loads the document at `path`. Encrypted documents are decrypted in memory with the
+    /// session's key, never to disk, and PDFs are rendered in memory too.
+    fn new(path: &Path, key: Option<&Key>) -> PreviewPane {
+        log(ActivityEvent::new("Preview").path(path));
+        let preview = match Self::load(path, key) {
+            Ok(images) => Preview::Images(images),
+            Err(reason) => {
+                log(ActivityEvent::new("PreviewFailed").path(path).detail(&reason));
+                Preview::Unavailable(reason)
+            }
+        };
+        PreviewPane {
+            preview_image_path: path.display().to_string(),
+            preview,
+            close_button: Default::default(),
+            scroll_state: Default::default(),
+        }
+    }
+
+    fn load(path: &Path, key: Option<&Key>) -> Result<Vec<image::Handle>, String> {
+        let pdf = utils::extension(crypto::decrypted_path(path)) == "pdf";
+        if !pdf && !crypto::is_encrypted(path) {
+            return Ok(vec![image::Handle::from_path(path)]);
+        }
+        let data = match key {
+            Some(key) => crypto::read_file(path, key).map_err(|e| e.to_string())?,
+            None if crypto::is_encrypted(path) => {
+                return Err("Enter the passphrase to preview encrypted documents.".to_string())
+            }
+            None => std::fs::read(path).map_err(|e| e.to_string())?,
+        };
+        if pdf {
+            render_pdf(&data)
+        } else {
+            Ok(vec![image::Handle::from_memory(data)])
+        }
+    }
+}
+
+/// Renders the first pages of a PDF into images in memory. The pdfium library is loaded from
+/// the executable's folder, or else from the system's libraries.
+#[cfg(not(target_arch = "wasm32"))]
+fn render_pdf(data: &[u8]) -> Result<Vec<image::Handle>, String> {
+    use pdfium_render::prelude::*;
+
+    let next_to_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(Pdfium::pdfium_platform_library_name_at_path(exe.parent()?)));
+    let bindings = next_to_exe
+        .and_then(|path| Pdfium::bind_to_library(path).ok())
+        .map_or_else(Pdfium::bind_to_system_library, Ok)
+        .map_err(|_| "Previewing PDF documents needs the pdfium library.".to_string())?;
+    let pdfium = Pdfium::new(bindings);
+    let document = pdfium
+        .load_pdf_from_byte_slice(data, None)
+        .map_err(|e| format!("Could not read the PDF: {:?}", e))?;
+    let config = PdfRenderConfig::new().set_target_width(PDF_PREVIEW_WIDTH);
+    document
+        .pages()
+        .iter()
+        .take(PDF_PREVIEW_PAGES)
+        .map(|page| {
+            let bitmap = page
+                .render_with_config(&config)
+                .map_err(|e| format!("Could not render the PDF: {:?}", e))?;
+            let mut pixels = bitmap.as_rgba_bytes();
+            // The image widget takes BGRA.
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+            let (width, height) = (bitmap.width() as u32, bitmap.height() as u32);
+            Ok(image::Handle::from_pixels(width, height, pixels))
+        })
+        .collect()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn render_pdf(_data: &[u8]) -> Result<Vec<image::Handle>, String> {
+    Err("PDF documents can't be previewed.".to_string())
+}
+
+/// Groups of byte-identical documents, each offering to keep one copy and delete the others.
+#[derive(Debug)]
+struct DuplicatesPane {
+    cabinet: Option<Cabinet>,
+    groups: Vec<Vec<DuplicateItem>>,
+    errors: Vec<String>,
+    close_button: button::State,
+    scroll_state: scrollable::State,
+}
+
+#[derive(Debug)]
+struct DuplicateItem {
+    doc: Document,
+    keep_button: button::State,
+}
+
+impl DuplicatesPane {
+    fn new(path: &str) -> DuplicatesPane {
+        let mut errors = Vec::new();
+        let (cabinet, groups) = match Cabinet::open(path) {
+            Ok(cabinet) => {
+                let groups = match cabinet.duplicates() {
+                    Ok(groups) => groups,
+                    Err(e) => {
+                        log(ActivityEvent::new("DuplicatesFailed").detail(&e));
+                        errors.push(format!("Could not look for duplicates: {}", e));
+                        Vec::new()
+                    }
+                };
+                (Some(cabinet), groups)
+            }
+            Err(e) => {
+                log(ActivityEvent::new("OpenFailed").path(path).detail(&e));
+                errors.push(format!("Could not open \"{}\": {}", path, e));
+                (None, Vec::new())
+            }
+        };
+        let groups = groups
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .map(|doc| DuplicateItem {
+                        doc,
+                        keep_button: Default::default(),
+                    })
+                    .collect()
+            })
+            .collect();
+        DuplicatesPane {
+            cabinet,
+            groups,
+            errors,
+            close_button: Default::default(),
+            scroll_state: Default::default(),
+        }
+    }
+}
+
+/// The documents in the cabinet's trash, each offering to be restored.
+#[derive(Debug)]
+struct TrashPane {
+    cabinet: Option<Cabinet>,
+    entries: Vec<TrashItem>,
+    errors: Vec<String>,
+    empty_button: button::State,
+    close_button: button::State,
+    scroll_state: scrollable::State,
+}
+
+#[derive(Debug)]
+struct TrashItem {
+    entry: TrashEntry,
+    restore_button: button::State,
+}
+
+impl TrashPane {
+    fn new(path: &str) -> TrashPane {
+        let mut pane = TrashPane {
+            cabinet: None,
+            entries: Vec::new(),
+            errors: Vec::new(),
+            empty_button: Default::default(),
+            close_button: Default::default(),
+            scroll_state: Default::default(),
+        };
+        match Cabinet::open(path) {
+            Ok(cabinet) => pane.cabinet = Some(cabinet),
+            Err(e) => {
+                log(ActivityEvent::new("OpenFailed").path(path).detail(&e));
+                pane.errors
+                    .push(format!("Could not open \"{}\": {}", path, e));
+            }
+        }
+        pane.list();
+        pane
+    }
+
+    fn list(&mut self) {
+        let entries = match self.cabinet.as_ref().map(Cabinet::trash) {
+            Some(Ok(entries)) => entries,
+            Some(Err(e)) => {
+                log(ActivityEvent::new("TrashFailed").detail(&e));
+                self.errors.push(format!("Could not read the trash: {}", e));
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        self.entries = entries
+            .into_iter()
+            .map(|entry| TrashItem {
+                entry,
+                restore_button: Default::default(),
+            })
+            .collect();
+    }
+}
+
+/// The most recent events of the activity log, newest first, optionally of a single kind.
+#[derive(Debug, Default)]
+struct ActivityPane {
+    events: Vec<ActivityEvent>,
+    /// How far the log has been read, to only read the events logged since.
+    offset: u64,
+    /// The kinds of the events, to pick the one to show.
+    kinds: Vec<String>,
+    kind: Option<String>,
+    kind_list: pick_list::State<String>,
+    errors: Vec<String>,
     close_button: button::State,
     scroll_state: scrollable::State,
 }
 
+impl ActivityPane {
+    fn new() -> ActivityPane {
+        let mut pane = ActivityPane::default();
+        match SavedState::activity_log().recent(ACTIVITY_LINES) {
+            Ok((events, offset)) => {
+                pane.events = events;
+                pane.offset = offset;
+            }
+            Err(e) => {
+                pane.errors
+                    .push(format!("Could not read the activity log: {}", e));
+            }
+        }
+        pane.list_kinds();
+        pane
+    }
+
+    /// Adds the events logged since the log was last read.
+    fn read_new(&mut self) {
+        match SavedState::activity_log().since(self.offset) {
+            Ok((events, offset)) => {
+                self.offset = offset;
+                if events.is_empty() {
+                    return;
+                }
+                self.events.extend(events);
+                let skipped = self.events.len().saturating_sub(ACTIVITY_LINES);
+                self.events.drain(..skipped);
+                self.list_kinds();
+            }
+            Err(e) => {
+                self.errors
+                    .push(format!("Could not read the activity log: {}", e));
+            }
+        }
+    }
+
+    fn list_kinds(&mut self) {
+        let mut kinds: Vec<String> = self.events.iter().map(|event| event.kind.clone()).collect();
+        kinds.sort();
+        kinds.dedup();
+        kinds.insert(0, ALL_KINDS.to_string());
+        self.kinds = kinds;
+    }
+}
+
 trait PaneContent {
     fn update(&mut self, message: Message);
-    fn view(&mut self, pane: Pane) -> Element<Message>;
+    fn view(&mut self, pane: Pane) -> Element<'_, Message>;
+
+    /// The errors the pane ran into since it was last asked, for the window's status area.
+    fn take_errors(&mut self) -> Vec<String> {
+        Vec::new()
+    }
 }
 
 impl PaneContent for PreviewPane {
     fn update(&mut self, _message: Message) {}
     fn view(&mut self, pane: Pane) -> Element<'_, Message> {
-        println!(
-            "event=preview_pane_opened image=\"{}\"",
-            &self.preview_image_path
-        );
+        let preview: Element<_> = match &self.preview {
+            Preview::Images(handles) => handles
+                .iter()
+                .fold(Column::new().spacing(10), |column, handle| {
+                    column.push(Image::new(handle.clone()))
+                })
+                .into(),
+            Preview::Unavailable(reason) => Text::new(reason.as_str()).into(),
+        };
         Column::new()
             .push(
                 Button::new(&mut self.close_button, Text::new("X").size(10))
@@ -109,7 +662,7 @@ impl PaneContent for PreviewPane {
                 Scrollable::new(&mut self.scroll_state)
                     .push(
                         Row::new()
-                            .push(Image::new(&self.preview_image_path))
+                            .push(preview)
                             .align_items(Align::Center)
                             .width(Length::Fill),
                     )
@@ -120,24 +673,505 @@ impl PaneContent for PreviewPane {
     }
 }
 
+impl PaneContent for DuplicatesPane {
+    fn update(&mut self, message: Message) {
+        if let Message::KeepDuplicate(g, keep) = message {
+            if let (Some(group), Some(cabinet)) = (self.groups.get(g), &self.cabinet) {
+                let docs: Vec<Document> = group.iter().map(|item| item.doc.clone()).collect();
+                match cabinet.remove_duplicates(&docs, keep) {
+                    Ok(()) => {
+                        log(ActivityEvent::new("KeepDuplicate")
+                            .path(&docs[keep].path)
+                            .detail(format!("{} copies moved to the trash", docs.len() - 1)));
+                        self.groups.remove(g);
+                    }
+                    Err(e) => {
+                        log(ActivityEvent::new("KeepDuplicateFailed")
+                            .path(&docs[keep].path)
+                            .detail(&e));
+                        self.errors.push(format!("Could not remove duplicates: {}", e));
+                        // The copies that made it to the trash are gone, the others stay listed
+                        // to try again.
+                        self.groups[g].retain(|item| item.doc.path.exists());
+                        if self.groups[g].len() < 2 {
+                            self.groups.remove(g);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn view(&mut self, pane: Pane) -> Element<'_, Message> {
+        let summary = if self.groups.is_empty() {
+            "No duplicates found.".to_string()
+        } else {
+            format!("{} groups of identical documents", self.groups.len())
+        };
+        let groups = self.groups.iter_mut().enumerate().fold(
+            Column::new().spacing(20),
+            |column, (g, group)| {
+                column.push(group.iter_mut().enumerate().fold(
+                    Column::new().spacing(5),
+                    |column, (i, item)| {
+                        column.push(
+                            Row::new()
+                                .spacing(10)
+                                .align_items(Align::Center)
+                                .push(Text::new(&item.doc.filename).width(Length::Fill))
+                                .push(
+                                    Button::new(&mut item.keep_button, Text::new("Keep"))
+                                        .on_press(Message::KeepDuplicate(g, i))
+                                        .padding(8)
+                                        .style(style::Button::Update),
+                                ),
+                        )
+                    },
+                ))
+            },
+        );
+        Column::new()
+            .push(
+                Button::new(&mut self.close_button, Text::new("X").size(10))
+                    .padding(10)
+                    .style(style::Button::Destructive)
+                    .on_press(Message::CloseDuplicatesPane(pane)),
+            )
+            .push(Text::new(summary))
+            .push(Text::new("Keeping a copy moves the others in its group to the trash.").size(14))
+            .push(
+                Scrollable::new(&mut self.scroll_state)
+                    .push(groups)
+                    .width(Length::Fill),
+            )
+            .spacing(10)
+            .padding(10)
+            .into()
+    }
+}
+
+impl PaneContent for TrashPane {
+    fn update(&mut self, message: Message) {
+        let cabinet = match &self.cabinet {
+            Some(cabinet) => cabinet,
+            None => return,
+        };
+        match message {
+            Message::RestoreTrashed(i) => {
+                if let Some(item) = self.entries.get(i) {
+                    match cabinet.restore(&item.entry.name) {
+                        Ok(path) => log(ActivityEvent::new("Restore").path(path)),
+                        Err(e) => {
+                            let path = utils::path_from_string(&item.entry.path);
+                            log(ActivityEvent::new("RestoreFailed").path(&path).detail(&e));
+                            self.errors.push(format!(
+                                "Could not restore \"{}\": {}",
+                                path.display(),
+                                e
+                            ));
+                        }
+                    }
+                }
+            }
+            Message::EmptyTrash => match cabinet.empty_trash(None) {
+                Ok(entries) => log(
+                    ActivityEvent::new("EmptyTrash")
+                        .detail(format!("{} documents deleted", entries.len())),
+                ),
+                Err(e) => {
+                    log(ActivityEvent::new("EmptyTrashFailed").detail(&e));
+                    self.errors.push(format!("Could not empty the trash: {}", e));
+                }
+            },
+            Message::RefreshTargetDir(_) => {}
+            _ => return,
+        }
+        self.list();
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn view(&mut self, pane: Pane) -> Element<'_, Message> {
+        let is_empty = self.entries.is_empty();
+        let summary = if is_empty {
+            "The trash is empty.".to_string()
+        } else {
+            format!("{} documents in the trash", self.entries.len())
+        };
+        let entries = self.entries.iter_mut().enumerate().fold(
+            Column::new().spacing(5),
+            |column, (i, item)| {
+                column.push(
+                    Row::new()
+                        .spacing(10)
+                        .align_items(Align::Center)
+                        .push(
+                            Text::new(utils::path_from_string(&item.entry.path).to_string_lossy())
+                                .width(Length::Fill),
+                        )
+                        .push(
+                            Button::new(&mut item.restore_button, Text::new("Restore"))
+                                .on_press(Message::RestoreTrashed(i))
+                                .padding(8)
+                                .style(style::Button::Update),
+                        ),
+                )
+            },
+        );
+        let mut empty_button = Button::new(&mut self.empty_button, Text::new("Empty trash"))
+            .padding(8)
+            .style(style::Button::Destructive);
+        if !is_empty {
+            empty_button = empty_button.on_press(Message::EmptyTrash);
+        }
+        Column::new()
+            .push(
+                Button::new(&mut self.close_button, Text::new("X").size(10))
+                    .padding(10)
+                    .style(style::Button::Destructive)
+                    .on_press(Message::CloseTrashPane(pane)),
+            )
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Align::Center)
+                    .push(Text::new(summary).width(Length::Fill))
+                    .push(empty_button),
+            )
+            .push(Text::new("Emptying the trash deletes its documents for good.").size(14))
+            .push(
+                Scrollable::new(&mut self.scroll_state)
+                    .push(entries)
+                    .width(Length::Fill),
+            )
+            .spacing(10)
+            .padding(10)
+            .into()
+    }
+}
+
+impl PaneContent for ActivityPane {
+    fn update(&mut self, message: Message) {
+        match message {
+            Message::ActivityKindSelected(kind) if kind == ALL_KINDS => self.kind = None,
+            Message::ActivityKindSelected(kind) => self.kind = Some(kind),
+            Message::RefreshActivity => self.read_new(),
+            _ => {}
+        }
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn view(&mut self, pane: Pane) -> Element<'_, Message> {
+        let kind = &self.kind;
+        let events = self
+            .events
+            .iter()
+            .rev()
+            .filter(|event| kind.as_ref().is_none_or(|kind| &event.kind == kind))
+            .fold(Column::new().spacing(5), |column, event| {
+                let time = DateTime::parse_from_rfc3339(&event.time)
+                    .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|_| event.time.clone());
+                let mut text = Text::new(format!("{}  {}", time, event)).size(14);
+                if event.is_failure() {
+                    text = text.color([0.8, 0.2, 0.2]);
+                }
+                column.push(text)
+            });
+        let selected = self.kind.clone().unwrap_or_else(|| ALL_KINDS.to_string());
+        Column::new()
+            .push(
+                Button::new(&mut self.close_button, Text::new("X").size(10))
+                    .padding(10)
+                    .style(style::Button::Destructive)
+                    .on_press(Message::CloseActivityPane(pane)),
+            )
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Align::Center)
+                    .push(Text::new("Activity").width(Length::Fill))
+                    .push(PickList::new(
+                        &mut self.kind_list,
+                        &self.kinds[..],
+                        Some(selected),
+                        Message::ActivityKindSelected,
+                    )),
+            )
+            .push(
+                Scrollable::new(&mut self.scroll_state)
+                    .push(events)
+                    .width(Length::Fill),
+            )
+            .spacing(10)
+            .padding(10)
+            .into()
+    }
+}
+
+impl DocPane {
+    fn open(&mut self, path: &str) {
+        match Cabinet::open(path) {
+            Ok(cabinet) => {
+                // Documents waiting on a rename conflict stay as they're being edited.
+                let mut conflicts: Vec<DocItem> = self
+                    .docs
+                    .drain(..)
+                    .filter(|item| item.conflict.is_some())
+                    .collect();
+                let listed = match Self::list(&cabinet, self.inbox, self.sort) {
+                    Ok(listed) => listed,
+                    Err(e) => {
+                        log(ActivityEvent::new("ListFailed").path(path).detail(&e));
+                        self.errors
+                            .push(format!("Could not list the documents: {}", e));
+                        Vec::new()
+                    }
+                };
+                self.docs = listed
+                    .into_iter()
+                    .map(|item| {
+                        match conflicts.iter().position(|c| c.doc.path == item.doc.path) {
+                            Some(i) => conflicts.swap_remove(i),
+                            None => item,
+                        }
+                    })
+                    .collect();
+                self.cabinet = Some(cabinet);
+            }
+            Err(e) => {
+                log(ActivityEvent::new("OpenFailed").path(path).detail(&e));
+                self.errors.push(format!("Could not open \"{}\": {}", path, e));
+                self.docs = Vec::new();
+                self.cabinet = None;
+            }
+        }
+    }
+
+    fn list(cabinet: &Cabinet, inbox: bool, sort: SortOrder) -> filecabinet::Result<Vec<DocItem>> {
+        let mut docs = if inbox {
+            cabinet.inbox_documents()?
+        } else {
+            cabinet.documents()?
+        };
+        sort.sort(&mut docs, |doc| doc);
+        Ok(docs.into_iter().map(DocItem::new).collect())
+    }
+
+    /// Files documents into the folders of the cabinet's layout.
+    fn file(&mut self, docs: &[Document]) {
+        let cabinet = match &self.cabinet {
+            Some(cabinet) => cabinet,
+            None => return,
+        };
+        for doc in docs {
+            // Filing an earlier document may have moved this one along as one of its pages.
+            if !std::path::Path::new(&doc.path).exists() {
+                continue;
+            }
+            match cabinet.file(doc, ConflictPolicy::Cancel) {
+                Ok(moved) => {
+                    for (old, new) in moved {
+                        log(ActivityEvent::new("File").path(old).new_path(new));
+                    }
+                }
+                Err(e) => {
+                    log(ActivityEvent::new("FileFailed").path(&doc.path).detail(&e));
+                    self.errors.push(format!("Could not file \"{}\": {}", doc.path.display(), e));
+                }
+            }
+        }
+    }
+
+    /// Renames the i-th document after its fields were edited, or archives it from the inbox.
+    /// If another file already has its new name, the edit stays open and offers the choices of
+    /// `ConflictPolicy`.
+    fn finish_edition(&mut self, i: usize, policy: ConflictPolicy) {
+        if let (Some(doc), Some(cabinet)) = (self.docs.get_mut(i), &self.cabinet) {
+            let old_path = doc.doc.path.clone();
+            let (event, result) = if self.inbox {
+                ("Archive", cabinet.archive(&mut doc.doc, policy))
+            } else {
+                ("Rename", cabinet.rename(&mut doc.doc, policy))
+            };
+            match result {
+                Ok(new_path) => log(ActivityEvent::new(event).path(&old_path).new_path(new_path)),
+                Err(e) => match e.kind() {
+                    ErrorKind::Exists(_) | ErrorKind::NotIdentical(_) => {
+                        let kind = format!("{}Conflict", event);
+                        log(ActivityEvent::new(&kind).path(&old_path).detail(&e));
+                        doc.conflict = Some(e.to_string());
+                        return;
+                    }
+                    _ => {
+                        let kind = format!("{}Failed", event);
+                        log(ActivityEvent::new(&kind).path(&old_path).detail(&e));
+                        self.errors.push(format!(
+                            "Could not {} \"{}\": {}",
+                            event.to_lowercase(),
+                            old_path.display(),
+                            e
+                        ));
+                    }
+                },
+            }
+            doc.update(DocMessage::FinishEdition);
+        }
+    }
+
+    /// Encrypts or decrypts the i-th document with the session's key.
+    fn encrypt(&mut self, i: usize, encrypt: bool) {
+        let cabinet = match &self.cabinet {
+            Some(cabinet) => cabinet,
+            None => return,
+        };
+        if let (Some(item), Some(key)) = (self.docs.get_mut(i), &self.key) {
+            let (event, result) = if encrypt {
+                ("Encrypt", cabinet.encrypt(&mut item.doc, key))
+            } else {
+                ("Decrypt", cabinet.decrypt(&mut item.doc, key))
+            };
+            match result {
+                Ok(new_path) => log(ActivityEvent::new(event).path(new_path)),
+                Err(e) => {
+                    let kind = format!("{}Failed", event);
+                    log(ActivityEvent::new(&kind).path(&item.doc.path).detail(&e));
+                    self.errors.push(format!(
+                        "Could not {} \"{}\": {}",
+                        event.to_lowercase(),
+                        item.doc.path.display(),
+                        e
+                    ));
+                }
+            }
+        }
+    }
+}
+
 impl PaneContent for DocPane {
     fn update(&mut self, message: Message) {
         match message {
             Message::Loaded(_) => {}
             Message::Saved(_) => {}
-            Message::RefreshTargetDir(path) => self.docs = utils::read_docs(&path),
-            Message::PathChanged(path) => self.docs = utils::read_docs(&path),
+            Message::RefreshTargetDir(path) => self.open(&path),
+            Message::PathChanged(path) => {
+                self.key = None;
+                self.open(&path);
+            }
             Message::FilterChanged(filter) => {
                 self.filter = filter;
             }
-            Message::DocMessage(i, DocMessage::ConfirmDelete) => {
-                if let Some(doc) = self.docs.get_mut(i) {
+            Message::FolderChanged(folder) => {
+                self.folder = folder;
+            }
+            Message::SearchChanged(search) => {
+                self.query = Query::parse(&search);
+                self.search = search;
+            }
+            Message::FacetSelected(choice) => self.facets.set(choice.facet, choice.value),
+            Message::SortChanged(sort) => {
+                self.sort = sort;
+                sort.sort(&mut self.docs, |item| &item.doc);
+            }
+            Message::GroupByChanged(group_by) => self.group_by = group_by,
+            Message::ToggleGroup(_, key) => {
+                if self.collapsed.contains(&key) {
+                    self.collapsed.remove(&key);
+                } else {
+                    self.collapsed.insert(key);
+                }
+            }
+            Message::RecursiveToggled(recursive) if !self.inbox => {
+                let sort = self.sort;
+                if let Some(cabinet) = &mut self.cabinet {
+                    let mut config = cabinet.config().clone();
+                    config.recursive = recursive;
+                    let listed = cabinet
+                        .set_config(config)
+                        .and_then(|_| Self::list(cabinet, false, sort));
+                    match listed {
+                        Ok(docs) => self.docs = docs,
+                        Err(e) => {
+                            log(ActivityEvent::new("ConfigFailed").detail(&e));
+                            self.errors.push(format!("Could not change the config: {}", e));
+                        }
+                    }
+                }
+            }
+            Message::PassphraseChanged(_) => self.key = None,
+            Message::KeyUnlocked(key) => self.key = Some(key),
+            Message::EncryptSelected(encrypt) => {
+                for i in 0..self.docs.len() {
+                    let item = &self.docs[i];
+                    if item.selected && item.doc.encrypted != encrypt {
+                        self.encrypt(i, encrypt);
+                    }
+                }
+            }
+            Message::DocMessage(_, i, DocMessage::Encrypt(encrypt)) => self.encrypt(i, encrypt),
+            Message::DocMessage(_, i, DocMessage::File) => {
+                if let Some(item) = self.docs.get(i) {
+                    let doc = item.doc.clone();
+                    self.file(&[doc]);
+                }
+            }
+            Message::FileSelected => {
+                let selected: Vec<Document> = self
+                    .docs
+                    .iter()
+                    .filter(|item| item.selected)
+                    .map(|item| item.doc.clone())
+                    .collect();
+                self.file(&selected);
+            }
+            Message::DocMessage(_, i, DocMessage::ConfirmDelete) => {
+                if let (Some(doc), Some(cabinet)) = (self.docs.get_mut(i), &self.cabinet) {
                     doc.update(DocMessage::ConfirmDelete);
-                    fs::remove_file(doc.clone().path).unwrap();
+                    match cabinet.delete(&doc.doc) {
+                        Ok(path) => {
+                            log(ActivityEvent::new("Delete").path(&doc.doc.path).new_path(path));
+                            self.docs.remove(i);
+                        }
+                        Err(e) => {
+                            log(ActivityEvent::new("DeleteFailed").path(&doc.doc.path).detail(&e));
+                            let path = doc.doc.path.display();
+                            self.errors.push(format!("Could not delete \"{}\": {}", path, e));
+                        }
+                    }
                 }
-                self.docs.remove(i);
             }
-            Message::DocMessage(i, doc_message) => {
+            Message::DocMessage(_, i, DocMessage::ConfirmShred) => {
+                if let (Some(doc), Some(cabinet)) = (self.docs.get_mut(i), &self.cabinet) {
+                    doc.update(DocMessage::ConfirmShred);
+                    match cabinet.shred(&doc.doc, None) {
+                        Ok(()) => {
+                            log(ActivityEvent::new("Shred").path(&doc.doc.path));
+                            self.docs.remove(i);
+                        }
+                        Err(e) => {
+                            log(ActivityEvent::new("ShredFailed").path(&doc.doc.path).detail(&e));
+                            let path = doc.doc.path.display();
+                            self.errors.push(format!("Could not shred \"{}\": {}", path, e));
+                        }
+                    }
+                }
+            }
+            Message::DocMessage(_, i, DocMessage::FinishEdition) => {
+                self.finish_edition(i, ConflictPolicy::Cancel)
+            }
+            Message::DocMessage(_, i, DocMessage::ResolveConflict(policy)) => {
+                self.finish_edition(i, policy)
+            }
+            Message::DocMessage(_, i, doc_message) => {
                 if let Some(doc) = self.docs.get_mut(i) {
                     doc.update(doc_message);
                 }
@@ -146,30 +1180,100 @@ impl PaneContent for DocPane {
         }
     }
 
-    fn view(&mut self, pane: Pane) -> Element<Message> {
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn view(&mut self, pane: Pane) -> Element<'_, Message> {
         let DocPane {
+            inbox,
             docs,
             filter,
+            folder,
+            folder_input,
+            search,
+            query,
+            facets,
+            sort,
+            group_by,
+            collapsed,
+            header_buttons,
             controls,
+            cabinet,
             ..
         } = self;
 
-        let controls = controls.view(&docs, *filter);
-        let filtered_docs = docs.iter().filter(|doc| filter.matches(doc));
-
-        let docs: Element<_> = if filtered_docs.count() > 0 {
+        let controls = controls.view(docs, *filter, search, facets, *sort, *group_by);
+        let recursive = cabinet
+            .as_ref()
+            .is_some_and(|c| c.config().scans_subfolders());
+        let folders = Row::new()
+            .spacing(20)
+            .align_items(Align::Center)
+            .push(
+                TextInput::new(
+                    folder_input,
+                    "Folder, e.g. 2021/Chase",
+                    folder,
+                    Message::FolderChanged,
+                )
+                .padding(8)
+                .size(16),
+            )
+            .push(Checkbox::new(
+                recursive,
+                "Include subfolders",
+                Message::RecursiveToggled,
+            ));
+        // The inbox has no subfolders, but the number of documents waiting in it matters.
+        let header: Element<_> = if *inbox {
+            Text::new(format!("Inbox: {} documents", docs.len()))
+                .size(20)
+                .into()
+        } else {
+            folders.into()
+        };
+        let matcher = filter.and(&*query).and(&*facets);
+        let visible = |item: &DocItem| matcher.matches(&item.doc) && item.doc.is_in(folder);
+        let any_visible = docs.iter().any(&visible);
+
+        let docs: Element<_> = if any_visible && *group_by != GroupBy::Nothing {
+            let shown: Vec<usize> = (0..docs.len()).filter(|&i| visible(&docs[i])).collect();
+            let shown_docs: Vec<&Document> = shown.iter().map(|&i| &docs[i].doc).collect();
+            let groups = group_by.group(&shown_docs);
+            let mut keys = HashSet::new();
+            group_keys(&groups, "", &mut keys);
+            header_buttons.retain(|key, _| keys.contains(key));
+            for key in keys {
+                header_buttons.entry(key).or_default();
+            }
+            GroupList {
+                shown: &shown,
+                docs: docs.iter_mut().map(Some).collect(),
+                buttons: header_buttons
+                    .iter_mut()
+                    .map(|(key, state)| (key.as_str(), state))
+                    .collect(),
+                collapsed,
+                pane,
+            }
+            .push(Column::new().spacing(0), &groups, "", 0)
+            .into()
+        } else if any_visible {
             docs.iter_mut()
                 .enumerate()
-                .filter(|(_, doc)| filter.matches(doc))
+                .filter(|(_, item)| visible(item))
                 .fold(Column::new().spacing(0), |column, (i, doc)| {
                     column.push(
                         doc.view(&pane)
-                            .map(move |message| Message::DocMessage(i, message)),
+                            .map(move |message| Message::DocMessage(pane, i, message)),
                     )
                 })
                 .into()
         } else {
             empty_message(match filter {
+                _ if !query.is_empty() => "No files match the search...",
+                _ if !facets.is_empty() => "No files match the picked facets...",
                 Filter::All => "No files found...",
                 Filter::Normalized => "No files found...",
                 Filter::Unnormalized => "No files found...",
@@ -180,6 +1284,7 @@ impl PaneContent for DocPane {
             .max_width(800)
             .spacing(20)
             .push(controls)
+            .push(header)
             .push(docs);
 
         Scrollable::new(&mut self.scroll)
@@ -189,6 +1294,79 @@ impl PaneContent for DocPane {
     }
 }
 
+/// The keys of `groups` and their subgroups in `DocPane::collapsed`.
+fn group_keys(groups: &[Group], parent: &str, keys: &mut HashSet<String>) {
+    for group in groups {
+        let key = format!("{}{}", parent, group.value);
+        group_keys(&group.subgroups, &format!("{}/", key), keys);
+        keys.insert(key);
+    }
+}
+
+/// Lists a pane's documents under collapsible headers with the number of documents in each.
+struct GroupList<'a, 'b> {
+    /// The indices in the pane of the documents that are grouped.
+    shown: &'b [usize],
+    /// The pane's documents, each taken when it's listed.
+    docs: Vec<Option<&'a mut DocItem>>,
+    buttons: HashMap<&'a str, &'a mut button::State>,
+    collapsed: &'b HashSet<String>,
+    pane: Pane,
+}
+
+impl<'a, 'b> GroupList<'a, 'b> {
+    fn push(
+        &mut self,
+        mut column: Column<'a, Message>,
+        groups: &[Group],
+        parent: &str,
+        depth: u16,
+    ) -> Column<'a, Message> {
+        let pane = self.pane;
+        for group in groups {
+            let key = format!("{}{}", parent, group.value);
+            let collapsed = self.collapsed.contains(&key);
+            let label = format!(
+                "{} {}: {}",
+                if collapsed { "+" } else { "-" },
+                if group.value.is_empty() {
+                    "None"
+                } else {
+                    &group.value
+                },
+                group.docs.len()
+            );
+            if let Some(state) = self.buttons.remove(key.as_str()) {
+                column = column.push(
+                    Row::new().push(Space::with_width(Length::Units(20 * depth))).push(
+                        Button::new(state, Text::new(label).size(18))
+                            .on_press(Message::ToggleGroup(pane, key.clone()))
+                            .padding(8)
+                            .style(style::Button::Filter { selected: false }),
+                    ),
+                );
+            }
+            if collapsed {
+                continue;
+            }
+            if !group.subgroups.is_empty() {
+                column = self.push(column, &group.subgroups, &format!("{}/", key), depth + 1);
+                continue;
+            }
+            for &j in &group.docs {
+                let i = self.shown[j];
+                if let Some(doc) = self.docs[i].take() {
+                    column = column.push(
+                        doc.view(&pane)
+                            .map(move |message| Message::DocMessage(pane, i, message)),
+                    );
+                }
+            }
+        }
+        column
+    }
+}
+
 impl Application for FileCabinet {
     type Executor = iced::executor::Default;
     type Message = Message;
@@ -201,6 +1379,25 @@ impl Application for FileCabinet {
         )
     }
 
+    /// Ctrl+Z undoes and Ctrl+Shift+Z or Ctrl+Y redo, unless a text input has the key.
+    fn subscription(&self) -> Subscription<Message> {
+        iced_native::subscription::events_with(|event, status| match (event, status) {
+            (
+                Event::Keyboard(keyboard::Event::KeyPressed {
+                    key_code,
+                    modifiers,
+                }),
+                event::Status::Ignored,
+            ) if modifiers.control || modifiers.logo => match key_code {
+                KeyCode::Z if modifiers.shift => Some(Message::Redo),
+                KeyCode::Z => Some(Message::Undo),
+                KeyCode::Y => Some(Message::Redo),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
     fn title(&self) -> String {
         let dirty = match self {
             FileCabinet::Loading => false,
@@ -222,18 +1419,25 @@ impl Application for FileCabinet {
                             as Box<dyn PaneContent>);
                         // Pass the path to each doc_pane doc so it can render.
                         for (_pane, boxed_content) in pane_state.iter_mut() {
+                            boxed_content.update(Message::SortChanged(saved_state.sort));
+                            boxed_content.update(Message::GroupByChanged(saved_state.group_by));
                             boxed_content
                                 .update(Message::PathChanged(saved_state.target_dir.clone()));
                         }
-                        *self = FileCabinet::Loaded(State {
+                        let mut state = Box::new(State {
                             target_dir: saved_state.target_dir,
+                            sort: saved_state.sort,
+                            group_by: saved_state.group_by,
                             panes: pane_state,
                             doc_pane: Some(pane),
                             ..Default::default()
                         });
+                        state.remove_temp_files();
+                        state.sync_cabinet();
+                        *self = FileCabinet::Loaded(state);
                     }
                     Message::Loaded(Err(_)) => {
-                        *self = FileCabinet::Loaded(State::default());
+                        *self = FileCabinet::Loaded(Box::default());
                     }
                     _ => {}
                 }
@@ -244,38 +1448,201 @@ impl Application for FileCabinet {
 
                 match message {
                     Message::RefreshTargetDir(_) => {
+                        state.remove_temp_files();
                         for (_pane, boxed_content) in state.panes.iter_mut() {
                             boxed_content.update(message.clone());
                         }
+                        state.sync_cabinet();
                     }
                     Message::PathChanged(ref value) => {
                         state.target_dir = value.clone();
+                        state.key = None;
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(message.clone());
+                        }
+                        state.sync_cabinet();
+                    }
+                    Message::SortChanged(sort) => {
+                        state.sort = sort;
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(message.clone());
+                        }
+                    }
+                    Message::GroupByChanged(group_by) => {
+                        state.group_by = group_by;
                         for (_pane, boxed_content) in state.panes.iter_mut() {
                             boxed_content.update(message.clone());
                         }
                     }
+                    Message::ToggleGroup(pane, _) => {
+                        if let Some(content) = state.panes.get_mut(&pane) {
+                            content.update(message.clone());
+                        }
+                    }
                     Message::FilterChanged(_filter) => {
                         for (_pane, boxed_content) in state.panes.iter_mut() {
                             boxed_content.update(message.clone());
                         }
                     }
+                    Message::FolderChanged(_)
+                    | Message::SearchChanged(_)
+                    | Message::FacetSelected(_)
+                    | Message::RecursiveToggled(_) => {
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(message.clone());
+                        }
+                    }
+                    Message::PassphraseChanged(ref passphrase) => {
+                        state.passphrase = passphrase.clone();
+                        state.key = None;
+                        // Let the preview be reopened with the new passphrase.
+                        state.preview_image = PathBuf::new();
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(message.clone());
+                        }
+                    }
+                    Message::RepeatedPassphraseChanged(passphrase) => {
+                        state.repeated_passphrase = passphrase
+                    }
+                    Message::CreateKey => state.create_key(),
+                    Message::EncryptSelected(_) => {
+                        // The panes are given the key if it can be unlocked, and leave the
+                        // documents alone otherwise.
+                        state.key();
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(message.clone());
+                        }
+                    }
+                    Message::DocMessage(pane, _, DocMessage::Encrypt(_)) => {
+                        state.key();
+                        if let Some(content) = state.panes.get_mut(&pane) {
+                            content.update(message.clone());
+                        }
+                    }
+                    Message::Export => {
+                        match Cabinet::open(&state.target_dir).and_then(|cabinet| {
+                            Ok(export::write_catalog(&cabinet.documents()?, cabinet.root())?)
+                        }) {
+                            Ok(paths) => {
+                                for path in paths {
+                                    log(ActivityEvent::new("Export").path(path));
+                                }
+                            }
+                            Err(e) => {
+                                log(ActivityEvent::new("ExportFailed").detail(&e));
+                                state.report(format!("Could not export the catalog: {}", e));
+                            }
+                        }
+                    }
                     Message::ClosePreviewPane(pane) => {
                         state.panes.close(&pane);
                         state.preview_pane = Default::default();
                     }
-                    Message::DocMessage(_, DocMessage::OpenPreviewPane(path, _)) => {
+                    Message::FindDuplicates => {
+                        if let Some(pane) = state.duplicates_pane.take() {
+                            state.panes.close(&pane);
+                        }
+                        if let Some(doc_pane) = &state.doc_pane {
+                            state.duplicates_pane = state
+                                .panes
+                                .split(
+                                    pane_grid::Axis::Vertical,
+                                    doc_pane,
+                                    Box::new(DuplicatesPane::new(&state.target_dir)),
+                                )
+                                .map(|(pane, _)| pane);
+                        }
+                    }
+                    Message::KeepDuplicate(_, _) => {
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(message.clone());
+                        }
+                        // Refresh after deleting
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(Message::RefreshTargetDir(state.target_dir.clone()));
+                        }
+                    }
+                    Message::CloseDuplicatesPane(pane) => {
+                        state.panes.close(&pane);
+                        state.duplicates_pane = None;
+                    }
+                    Message::OpenTrash => {
+                        if let Some(pane) = state.trash_pane.take() {
+                            state.panes.close(&pane);
+                        }
+                        if let Some(doc_pane) = &state.doc_pane {
+                            state.trash_pane = state
+                                .panes
+                                .split(
+                                    pane_grid::Axis::Vertical,
+                                    doc_pane,
+                                    Box::new(TrashPane::new(&state.target_dir)),
+                                )
+                                .map(|(pane, _)| pane);
+                        }
+                    }
+                    Message::RestoreTrashed(_) | Message::EmptyTrash => {
+                        if let Some(pane) = state.trash_pane {
+                            if let Some(content) = state.panes.get_mut(&pane) {
+                                content.update(message.clone());
+                            }
+                        }
+                        // Refresh after restoring
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(Message::RefreshTargetDir(state.target_dir.clone()));
+                        }
+                    }
+                    Message::CloseTrashPane(pane) => {
+                        state.panes.close(&pane);
+                        state.trash_pane = None;
+                    }
+                    Message::OpenActivity => {
+                        if let Some(pane) = state.activity_pane.take() {
+                            state.panes.close(&pane);
+                        }
+                        if let Some(doc_pane) = &state.doc_pane {
+                            state.activity_pane = state
+                                .panes
+                                .split(
+                                    pane_grid::Axis::Horizontal,
+                                    doc_pane,
+                                    Box::new(ActivityPane::new()),
+                                )
+                                .map(|(pane, _)| pane);
+                        }
+                    }
+                    Message::ActivityKindSelected(_) => {
+                        if let Some(pane) = state.activity_pane {
+                            if let Some(content) = state.panes.get_mut(&pane) {
+                                content.update(message.clone());
+                            }
+                        }
+                    }
+                    Message::CloseActivityPane(pane) => {
+                        state.panes.close(&pane);
+                        state.activity_pane = None;
+                    }
+                    Message::DismissStatus => state.status.clear(),
+                    Message::Undo | Message::Redo => {
+                        state.undo(matches!(message, Message::Redo));
+                        for (_pane, boxed_content) in state.panes.iter_mut() {
+                            boxed_content.update(Message::RefreshTargetDir(state.target_dir.clone()));
+                        }
+                    }
+                    Message::DocMessage(_, _, DocMessage::OpenPreviewPane(path, _)) => {
+                        let key = if crypto::is_encrypted(&path) && !state.passphrase.is_empty() {
+                            state.key()
+                        } else {
+                            None
+                        };
                         if let Some(doc_pane) = &state.doc_pane {
                             match state.preview_pane {
                                 None => {
-                                    println!("Preview pane closed, opening for the first time");
                                     // If the preview pane isn't open, open it,
                                     if let Some((preview_pane, _split)) = state.panes.split(
                                         pane_grid::Axis::Vertical,
                                         doc_pane,
-                                        Box::new(PreviewPane {
-                                            preview_image_path: path.clone(),
-                                            ..Default::default()
-                                        }),
+                                        Box::new(PreviewPane::new(&path, key.as_ref())),
                                     ) {
                                         // then save the preview pane.
                                         state.preview_pane = Some(preview_pane);
@@ -283,19 +1650,14 @@ impl Application for FileCabinet {
                                     }
                                 }
                                 Some(preview_pane) => {
-                                    println!("Preview pane open, closing and reopening new one...");
                                     if state.preview_image != path {
-                                        println!("Preview pane image is the same path, refusing to open.");
                                         // If the preview pane is open, close it,
                                         state.panes.close(&preview_pane);
                                         // then open the new one.
                                         if let Some((pane, _)) = state.panes.split(
                                             pane_grid::Axis::Vertical,
                                             doc_pane,
-                                            Box::new(PreviewPane {
-                                                preview_image_path: path.clone(),
-                                                ..Default::default()
-                                            }),
+                                            Box::new(PreviewPane::new(&path, key.as_ref())),
                                         ) {
                                             // Update the preview pane with state.
                                             state.preview_pane = Some(pane);
@@ -303,34 +1665,50 @@ impl Application for FileCabinet {
                                         } else {
                                             // If fails, unset the preview pane.
                                             state.preview_pane = None;
-                                            state.preview_image = String::new();
+                                            state.preview_image = PathBuf::new();
                                         }
                                     }
                                 }
                             }
                         }
                     }
-                    Message::DocMessage(_, DocMessage::Delete) => {
-                        for (_pane, boxed_content) in state.panes.iter_mut() {
-                            boxed_content.update(message.clone());
+                    Message::DocMessage(pane, _, DocMessage::ConfirmDelete) => {
+                        if let Some(content) = state.panes.get_mut(&pane) {
+                            content.update(message.clone());
                         }
-                        // Refresh after deleting
+                        // Refresh after deleting, which asks for confirmation first
                         for (_pane, boxed_content) in state.panes.iter_mut() {
                             boxed_content.update(Message::RefreshTargetDir(state.target_dir.clone()));
                         }
                     }
-                    Message::DocMessage(_, DocMessage::FinishEdition) => {
+                    Message::FileSelected | Message::DocMessage(_, _, DocMessage::File) => {
+                        for (pane, boxed_content) in state.panes.iter_mut() {
+                            match message {
+                                Message::DocMessage(from, _, _) if from != *pane => {}
+                                _ => boxed_content.update(message.clone()),
+                            }
+                        }
+                        // Refresh after filing
                         for (_pane, boxed_content) in state.panes.iter_mut() {
-                            boxed_content.update(message.clone());
+                            boxed_content.update(Message::RefreshTargetDir(state.target_dir.clone()));
+                        }
+                    }
+                    Message::DocMessage(
+                        pane,
+                        _,
+                        DocMessage::FinishEdition | DocMessage::ResolveConflict(_),
+                    ) => {
+                        if let Some(content) = state.panes.get_mut(&pane) {
+                            content.update(message.clone());
                         }
                         // Refresh after editing
                         for (_pane, boxed_content) in state.panes.iter_mut() {
                             boxed_content.update(Message::RefreshTargetDir(state.target_dir.clone()));
                         }
                     }
-                    Message::DocMessage(_, ref _doc_message) => {
-                        for (_pane, boxed_content) in state.panes.iter_mut() {
-                            boxed_content.update(message.clone());
+                    Message::DocMessage(pane, _, _) => {
+                        if let Some(content) = state.panes.get_mut(&pane) {
+                            content.update(message.clone());
                         }
                     }
                     Message::Resized(pane_grid::ResizeEvent { split, ratio }) => {
@@ -339,13 +1717,33 @@ impl Application for FileCabinet {
                     Message::Dragged(pane_grid::DragEvent::Dropped { pane, target }) => {
                         state.panes.swap(&pane, &target);
                     }
-                    Message::Saved(_) => {
+                    Message::Saved(result) => {
+                        if let Err(error) = result {
+                            log(ActivityEvent::new("SaveFailed").detail(format!("{:?}", error)));
+                            state.report("Could not save the window's state".to_string());
+                        }
                         state.saving = false;
                         saved = true;
                     }
                     _ => {}
                 }
 
+                let errors: Vec<String> = state
+                    .panes
+                    .iter_mut()
+                    .flat_map(|(_pane, content)| content.take_errors())
+                    .collect();
+                for error in errors {
+                    state.report(error);
+                }
+
+                // Show the events of the update in the activity pane.
+                if let Some(pane) = state.activity_pane.filter(|_| !saved) {
+                    if let Some(content) = state.panes.get_mut(&pane) {
+                        content.update(Message::RefreshActivity);
+                    }
+                }
+
                 if !saved {
                     state.dirty = true;
                 }
@@ -357,6 +1755,8 @@ impl Application for FileCabinet {
                     Command::perform(
                         SavedState {
                             target_dir: state.target_dir.clone(),
+                            sort: state.sort,
+                            group_by: state.group_by,
                         }
                         .save(),
                         Message::Saved,
@@ -368,7 +1768,7 @@ impl Application for FileCabinet {
         }
     }
 
-    fn view(&mut self) -> Element<Message> {
+    fn view(&mut self) -> Element<'_, Message> {
         match self {
             FileCabinet::Loading => loading_message(),
             FileCabinet::Loaded(state) => Container::new(
@@ -387,12 +1787,31 @@ impl Application for FileCabinet {
                                 TextInput::new(
                                     &mut state.target_dir_state,
                                     "Specify path to documents",
-                                    &*state.target_dir,
+                                    &state.target_dir,
                                     Message::PathChanged,
                                 )
+                                .on_submit(Message::RefreshTargetDir(state.target_dir.clone()))
+                                .padding(10)
+                                .size(16),
+                            )
+                            .push(
+                                TextInput::new(
+                                    &mut state.passphrase_state,
+                                    "Passphrase",
+                                    &state.passphrase,
+                                    Message::PassphraseChanged,
+                                )
+                                .password()
+                                .width(Length::Units(200))
                                 .padding(10)
                                 .size(16),
                             )
+                            .push(create_key_area(
+                                state.has_key,
+                                &state.repeated_passphrase,
+                                &mut state.repeated_passphrase_state,
+                                &mut state.create_key_state,
+                            ))
                             .push(
                                 Button::new(
                                     &mut state.refresh_state,
@@ -401,6 +1820,48 @@ impl Application for FileCabinet {
                                 .style(style::Button::Refresh)
                                 .padding(10)
                                 .on_press(Message::RefreshTargetDir(state.target_dir.clone())),
+                            )
+                            .push(
+                                Button::new(&mut state.export_state, Text::new("export").size(16))
+                                    .style(style::Button::Refresh)
+                                    .padding(10)
+                                    .on_press(Message::Export),
+                            )
+                            .push(
+                                Button::new(
+                                    &mut state.duplicates_state,
+                                    Text::new("duplicates").size(16),
+                                )
+                                .style(style::Button::Refresh)
+                                .padding(10)
+                                .on_press(Message::FindDuplicates),
+                            )
+                            .push(
+                                Button::new(&mut state.trash_state, Text::new("trash").size(16))
+                                    .style(style::Button::Refresh)
+                                    .padding(10)
+                                    .on_press(Message::OpenTrash),
+                            )
+                            .push(
+                                Button::new(
+                                    &mut state.activity_state,
+                                    Text::new("activity").size(16),
+                                )
+                                .style(style::Button::Refresh)
+                                .padding(10)
+                                .on_press(Message::OpenActivity),
+                            )
+                            .push(
+                                Button::new(&mut state.undo_state, Text::new("undo").size(16))
+                                    .style(style::Button::Refresh)
+                                    .padding(10)
+                                    .on_press(Message::Undo),
+                            )
+                            .push(
+                                Button::new(&mut state.redo_state, Text::new("redo").size(16))
+                                    .style(style::Button::Refresh)
+                                    .padding(10)
+                                    .on_press(Message::Redo),
                             ),
                     )
                     .push(
@@ -411,6 +1872,7 @@ impl Application for FileCabinet {
                         .on_resize(10, Message::Resized)
                         .spacing(10),
                     )
+                    .push(status_area(&state.status, &mut state.status_state))
                     .spacing(10),
             )
             .width(Length::Fill)
@@ -421,23 +1883,20 @@ impl Application for FileCabinet {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Document {
-    path: String,
-    filename: String,
-    date: String,
-    institution: String,
-    title: String,
-    page: String,
-    extension: String,
+/// A document row in the `DocPane` along with its widget state.
+#[derive(Debug, Clone)]
+pub struct DocItem {
+    doc: Document,
     selected: bool,
-    encrypted: bool,
     show_delete_confirmation: bool,
-    #[serde(skip)]
+    show_shred_confirmation: bool,
+    /// Why the last attempt to rename the document failed because of an existing file.
+    conflict: Option<String>,
     state: DocState,
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum DocState {
     Idle {
         edit_button: button::State,
@@ -448,11 +1907,17 @@ pub enum DocState {
         institution_input: text_input::State,
         title_input: text_input::State,
         page_input: text_input::State,
+        field_inputs: Vec<text_input::State>,
         delete_button: button::State,
+        shred_button: button::State,
         cancel_button: button::State,
         submit_button: button::State,
+        encrypt_button: button::State,
+        file_button: button::State,
         confirm_yes_button: button::State,
         confirm_no_button: button::State,
+        increment_button: button::State,
+        overwrite_button: button::State,
     },
 }
 
@@ -472,34 +1937,29 @@ pub enum DocMessage {
     DateEdited(String),
     InstitutionEdited(String),
     TitleEdited(String),
+    FieldEdited(String, String),
+    Encrypt(bool),
+    File,
     PageEdited(String),
     FinishEdition,
+    ResolveConflict(ConflictPolicy),
     Delete,
     ConfirmDelete,
+    Shred,
+    ConfirmShred,
     ConfirmNo,
     Cancel,
-    OpenPreviewPane(String, Pane),
+    OpenPreviewPane(PathBuf, Pane),
 }
 
-impl Document {
-    fn new(path: String) -> Self {
-        let options = OptDoc::new(&path);
-        let now: DateTime<Utc> = Utc::now();
-        let tmp = &path.clone();
-        let _path = Path::new(tmp);
-        let file_stem = _path.file_stem().unwrap().to_str().unwrap();
-        let extension = utils::extension(_path);
-        Document {
-            path,
-            filename: format!("{}.{}", file_stem, extension),
-            date: options.date.unwrap_or(now.format("%Y-%m-%d").to_string()),
-            institution: options.institution.unwrap_or(String::new()),
-            title: options.name.unwrap_or(String::new()),
-            page: options.page.unwrap_or(String::from("1")).parse().unwrap(),
-            extension: extension.to_string(),
+impl DocItem {
+    fn new(doc: Document) -> Self {
+        DocItem {
+            doc,
             selected: false,
-            encrypted: false,
             show_delete_confirmation: false,
+            show_shred_confirmation: false,
+            conflict: None,
             state: DocState::default(),
         }
     }
@@ -515,89 +1975,86 @@ impl Document {
                     institution_input: Default::default(),
                     title_input: Default::default(),
                     page_input: Default::default(),
+                    field_inputs: vec![Default::default(); self.doc.fields.len()],
                     delete_button: Default::default(),
+                    shred_button: Default::default(),
                     cancel_button: Default::default(),
                     submit_button: Default::default(),
+                    encrypt_button: Default::default(),
+                    file_button: Default::default(),
                     confirm_yes_button: Default::default(),
                     confirm_no_button: Default::default(),
+                    increment_button: Default::default(),
+                    overwrite_button: Default::default(),
                 };
             }
             DocMessage::Cancel => {
+                self.conflict = None;
                 self.state = DocState::Idle {
                     edit_button: button::State::new(),
                     preview_button: button::State::new(),
                 }
             }
             DocMessage::FinishEdition => {
-                self.institution = utils::to_camelcase(&*self.institution);
-                self.title = utils::to_camelcase(&*self.title);
-                let basename = Path::new(&self.path).parent();
-                let filename = format!(
-                    "{}_{}_{}_{}.{}",
-                    &self.date, &self.institution, &self.title, &self.page, &self.extension
-                );
-                let new_path: String = basename
-                    .and_then(|p| {
-                        // basename is a valid directory, add it and return.
-                        let mut pb = p.to_path_buf();
-                        pb.push(&filename);
-                        pb.to_str().map(|s| s.to_string())
-                    })
-                    .unwrap_or(filename);
-                fs::rename(&self.path, &new_path).unwrap(); // Rename file
-                println!(
-                    "event=\"Rename\" old=\"{}\" new=\"{}\"",
-                    &self.path, &new_path
-                );
-                self.path = new_path.to_string(); // Update UI doc path.
+                self.conflict = None;
                 self.state = DocState::Idle {
                     edit_button: button::State::new(),
                     preview_button: button::State::new(),
                 }
             }
             DocMessage::Delete => {
-                if self.show_delete_confirmation {
-                    self.show_delete_confirmation = false;
-                } else {
-                    self.show_delete_confirmation = true;
-                }
+                self.show_delete_confirmation = !self.show_delete_confirmation;
+                self.show_shred_confirmation = false;
+            }
+            DocMessage::Shred => {
+                self.show_shred_confirmation = !self.show_shred_confirmation;
+                self.show_delete_confirmation = false;
             }
             DocMessage::ConfirmNo => {
                 self.show_delete_confirmation = false;
+                self.show_shred_confirmation = false;
             }
             DocMessage::DateEdited(s) => {
-                self.date = s;
+                self.doc.date = s;
             }
             DocMessage::InstitutionEdited(s) => {
-                self.institution = s;
+                self.doc.institution = s;
             }
             DocMessage::PageEdited(s) => {
-                self.page = s;
+                self.doc.page = s;
             }
             DocMessage::TitleEdited(s) => {
-                self.title = s;
+                self.doc.title = s;
+            }
+            DocMessage::FieldEdited(name, s) => {
+                self.doc.set_field(&name, s);
             }
             _ => {}
         }
     }
 
-    fn view(&mut self, pane: &Pane) -> Element<DocMessage> {
+    fn view(&mut self, pane: &Pane) -> Element<'_, DocMessage> {
         match &mut self.state {
             DocState::Idle {
                 preview_button,
                 edit_button,
             } => {
                 let checkbox = Checkbox::new(self.selected, "", DocMessage::Selected);
-                let preview = Button::new(preview_button, Text::new(&self.filename))
-                    .on_press(DocMessage::OpenPreviewPane(self.path.clone(), *pane))
+                let preview = Button::new(preview_button, Text::new(self.doc.relative_path()))
+                    .on_press(DocMessage::OpenPreviewPane(self.doc.path.clone(), *pane))
                     .style(style::Button::Doc)
                     .width(Length::Fill);
-                Row::new()
+                let row = Row::new()
                     .spacing(20)
                     .align_items(Align::Center)
                     .push(checkbox)
-                    .push(preview)
-                    .push(
+                    .push(preview);
+                let row = if self.doc.encrypted {
+                    row.push(lock_icon())
+                } else {
+                    row
+                };
+                row.push(
                         Button::new(edit_button, edit_icon())
                             .on_press(DocMessage::Edit)
                             .padding(10)
@@ -610,17 +2067,37 @@ impl Document {
                 institution_input,
                 title_input,
                 page_input,
+                field_inputs,
                 delete_button,
+                shred_button,
                 cancel_button,
                 submit_button,
+                encrypt_button,
+                file_button,
                 confirm_no_button,
                 confirm_yes_button,
+                increment_button,
+                overwrite_button,
             } => {
+                // Custom fields from the cabinet's config.
+                let fields = self.doc.fields.iter().zip(field_inputs.iter_mut()).fold(
+                    Column::new().spacing(10),
+                    |column, ((name, value), input)| {
+                        let field = name.clone();
+                        column.push(
+                            TextInput::new(input, &utils::to_camelcase(name), value, move |s| {
+                                DocMessage::FieldEdited(field.clone(), s)
+                            })
+                            .on_submit(DocMessage::FinishEdition)
+                            .padding(10),
+                        )
+                    },
+                );
                 Column::new()
                     .spacing(10)
-                    .push(Text::new(&self.filename))
+                    .push(Text::new(&self.doc.filename))
                     .push(
-                        TextInput::new(date_input, "Date", &self.date, DocMessage::DateEdited)
+                        TextInput::new(date_input, "Date", &self.doc.date, DocMessage::DateEdited)
                             .on_submit(DocMessage::FinishEdition)
                             .padding(10),
                     )
@@ -628,19 +2105,20 @@ impl Document {
                         TextInput::new(
                             institution_input,
                             "Institution",
-                            &self.institution,
+                            &self.doc.institution,
                             DocMessage::InstitutionEdited,
                         )
                         .on_submit(DocMessage::FinishEdition)
                         .padding(10),
                     )
                     .push(
-                        TextInput::new(title_input, "Title", &self.title, DocMessage::TitleEdited)
+                        TextInput::new(title_input, "Title", &self.doc.title, DocMessage::TitleEdited)
                             .on_submit(DocMessage::FinishEdition)
                             .padding(10),
                     )
+                    .push(fields)
                     .push(
-                        TextInput::new(page_input, "Page", &self.page, DocMessage::PageEdited)
+                        TextInput::new(page_input, "Page", &self.doc.page, DocMessage::PageEdited)
                             .on_submit(DocMessage::FinishEdition)
                             .padding(10),
                     )
@@ -656,6 +2134,25 @@ impl Document {
                                 .padding(10)
                                 .style(style::Button::Update),
                             )
+                            .push(
+                                Button::new(
+                                    encrypt_button,
+                                    Text::new(if self.doc.encrypted {
+                                        "Decrypt"
+                                    } else {
+                                        "Encrypt"
+                                    }),
+                                )
+                                .on_press(DocMessage::Encrypt(!self.doc.encrypted))
+                                .padding(10)
+                                .style(style::Button::Update),
+                            )
+                            .push(
+                                Button::new(file_button, Text::new("File"))
+                                    .on_press(DocMessage::File)
+                                    .padding(10)
+                                    .style(style::Button::Update),
+                            )
                             // Delete Button
                             .push(
                                 Button::new(
@@ -669,7 +2166,14 @@ impl Document {
                                 .padding(10)
                                 .style(style::Button::Destructive),
                             )
-                            .push(if self.show_delete_confirmation {
+                            // Shred Button, for documents that mustn't be recoverable.
+                            .push(
+                                Button::new(shred_button, Text::new("Shred"))
+                                    .on_press(DocMessage::Shred)
+                                    .padding(10)
+                                    .style(style::Button::Destructive),
+                            )
+                            .push(if self.show_delete_confirmation || self.show_shred_confirmation {
                                 Row::new()
                                     .push(
                                         Button::new(confirm_no_button, Text::new("No!"))
@@ -678,7 +2182,11 @@ impl Document {
                                     )
                                     .push(
                                         Button::new(confirm_yes_button, Text::new("Yes?"))
-                                            .on_press(DocMessage::ConfirmDelete)
+                                            .on_press(if self.show_shred_confirmation {
+                                                DocMessage::ConfirmShred
+                                            } else {
+                                                DocMessage::ConfirmDelete
+                                            })
                                             .style(style::Button::Destructive),
                                     )
                                     .padding(10)
@@ -698,32 +2206,100 @@ impl Document {
                                 .style(style::Button::Cancel),
                             ),
                     )
+                    // Another file already has the new name, Cancel leaves both alone.
+                    .push(match &self.conflict {
+                        Some(conflict) => Row::new()
+                            .spacing(10)
+                            .align_items(Align::Center)
+                            .push(Text::new(conflict.as_str()).width(Length::Fill))
+                            .push(
+                                Button::new(increment_button, Text::new("Next page number"))
+                                    .on_press(DocMessage::ResolveConflict(
+                                        ConflictPolicy::Increment,
+                                    ))
+                                    .padding(10)
+                                    .style(style::Button::Update),
+                            )
+                            .push(
+                                Button::new(overwrite_button, Text::new("Overwrite if identical"))
+                                    .on_press(DocMessage::ResolveConflict(
+                                        ConflictPolicy::Overwrite,
+                                    ))
+                                    .padding(10)
+                                    .style(style::Button::Destructive),
+                            ),
+                        None => Row::new(),
+                    })
                     .into()
             }
         }
     }
 }
 
+/// An option of a facet's picker: a value with the number of documents that have it, or any
+/// value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FacetChoice {
+    facet: Facet,
+    value: Option<String>,
+    count: usize,
+}
+
+impl fmt::Display for FacetChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            None => write!(f, "Any {}", self.facet),
+            Some(value) if value.is_empty() => write!(f, "No {}: {}", self.facet, self.count),
+            Some(value) => write!(f, "{}: {}", value, self.count),
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Controls {
+    search_input: text_input::State,
+    /// The pickers of `Facet::ALL`, in order.
+    facet_lists: [pick_list::State<FacetChoice>; 4],
+    sort_list: pick_list::State<SortKey>,
+    direction_button: button::State,
+    group_list: pick_list::State<GroupBy>,
     all_button: button::State,
     active_button: button::State,
     completed_button: button::State,
+    encrypt_button: button::State,
+    decrypt_button: button::State,
+    file_button: button::State,
 }
 
 impl Controls {
-    fn view(&mut self, docs: &[Document], current_filter: Filter) -> Row<Message> {
+    fn view<'a>(
+        &'a mut self,
+        docs: &[DocItem],
+        current_filter: Filter,
+        search: &str,
+        facets: &Facets,
+        sort: SortOrder,
+        group_by: GroupBy,
+    ) -> Column<'a, Message> {
         let Controls {
+            search_input,
+            facet_lists,
+            sort_list,
+            direction_button,
+            group_list,
             all_button,
             active_button,
             completed_button,
+            encrypt_button,
+            decrypt_button,
+            file_button,
         } = self;
 
         let filter_button = |state, label, filter: Filter, current_filter: Filter| {
             let label = Text::new(format!(
                 "{}: {}",
                 label,
-                docs.iter().filter(|d| filter.matches(d)).count()
+                docs.iter().filter(|d| filter.matches(&d.doc)).count()
             ))
             .size(16);
             let button = Button::new(state, label).style(style::Button::Filter {
@@ -733,53 +2309,193 @@ impl Controls {
             button.on_press(Message::FilterChanged(filter)).padding(8)
         };
 
-        Row::new().spacing(20).align_items(Align::Center).push(
-            Row::new()
-                .width(Length::Shrink)
-                .spacing(10)
-                .push(filter_button(
-                    all_button,
-                    "All",
-                    Filter::All,
-                    current_filter,
-                ))
-                .push(filter_button(
-                    active_button,
-                    "Normalized",
-                    Filter::Normalized,
-                    current_filter,
-                ))
-                .push(filter_button(
-                    completed_button,
-                    "Unnormalized",
-                    Filter::Unnormalized,
-                    current_filter,
-                )),
+        let search = TextInput::new(
+            search_input,
+            "Search, e.g. chase year:2021 ext:pdf",
+            search,
+            Message::SearchChanged,
         )
+        .padding(8)
+        .size(16);
+        let pickers = Facet::ALL.iter().zip(facet_lists.iter_mut()).fold(
+            Row::new().spacing(10),
+            |row, (&facet, state)| {
+                let any = FacetChoice {
+                    facet,
+                    value: None,
+                    count: docs.len(),
+                };
+                let values = facet.counts(docs.iter().map(|item| &item.doc));
+                let choices: Vec<FacetChoice> = std::iter::once(any)
+                    .chain(values.into_iter().map(|(value, count)| FacetChoice {
+                        facet,
+                        value: Some(value),
+                        count,
+                    }))
+                    .collect();
+                let selected = choices
+                    .iter()
+                    .find(|choice| choice.value.as_deref() == facets.get(facet))
+                    .cloned();
+                row.push(
+                    PickList::new(state, choices, selected, Message::FacetSelected).text_size(16),
+                )
+            },
+        );
+        let direction = if sort.descending {
+            "Descending"
+        } else {
+            "Ascending"
+        };
+        let sorting = Row::new()
+            .spacing(10)
+            .align_items(Align::Center)
+            .push(Text::new("Sort by").size(16))
+            .push(
+                PickList::new(sort_list, &SortKey::ALL[..], Some(sort.key), move |key| {
+                    Message::SortChanged(SortOrder { key, ..sort })
+                })
+                .text_size(16),
+            )
+            .push(
+                Button::new(direction_button, Text::new(direction).size(16))
+                    .on_press(Message::SortChanged(SortOrder {
+                        descending: !sort.descending,
+                        ..sort
+                    }))
+                    .padding(8)
+                    .style(style::Button::Filter { selected: false }),
+            )
+            .push(Text::new("Group by").size(16))
+            .push(
+                PickList::new(
+                    group_list,
+                    &GroupBy::ALL[..],
+                    Some(group_by),
+                    Message::GroupByChanged,
+                )
+                .text_size(16),
+            );
+        let buttons = Row::new()
+            .spacing(20)
+            .align_items(Align::Center)
+            .push(
+                Row::new()
+                    .width(Length::Shrink)
+                    .spacing(10)
+                    .push(filter_button(
+                        all_button,
+                        "All",
+                        Filter::All,
+                        current_filter,
+                    ))
+                    .push(filter_button(
+                        active_button,
+                        "Normalized",
+                        Filter::Normalized,
+                        current_filter,
+                    ))
+                    .push(filter_button(
+                        completed_button,
+                        "Unnormalized",
+                        Filter::Unnormalized,
+                        current_filter,
+                    )),
+            )
+            .push(
+                Row::new()
+                    .width(Length::Shrink)
+                    .spacing(10)
+                    .push(
+                        Button::new(encrypt_button, Text::new("Encrypt selected").size(16))
+                            .on_press(Message::EncryptSelected(true))
+                            .padding(8)
+                            .style(style::Button::Filter { selected: false }),
+                    )
+                    .push(
+                        Button::new(decrypt_button, Text::new("Decrypt selected").size(16))
+                            .on_press(Message::EncryptSelected(false))
+                            .padding(8)
+                            .style(style::Button::Filter { selected: false }),
+                    )
+                    .push(
+                        Button::new(file_button, Text::new("File selected").size(16))
+                            .on_press(Message::FileSelected)
+                            .padding(8)
+                            .style(style::Button::Filter { selected: false }),
+                    ),
+            );
+        Column::new()
+            .spacing(10)
+            .push(search)
+            .push(pickers)
+            .push(sorting)
+            .push(buttons)
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Filter {
-    All,
-    Normalized,
-    Unnormalized,
-}
-
-impl Default for Filter {
-    fn default() -> Self {
-        Filter::All
+/// The passphrase again and a button to create the cabinet's key with it, unless it has one.
+fn create_key_area<'a>(
+    has_key: bool,
+    repeated_passphrase: &str,
+    input: &'a mut text_input::State,
+    create: &'a mut button::State,
+) -> Element<'a, Message> {
+    if has_key {
+        return Row::new().into();
     }
+    Row::new()
+        .spacing(10)
+        .push(
+            TextInput::new(
+                input,
+                "Repeat passphrase",
+                repeated_passphrase,
+                Message::RepeatedPassphraseChanged,
+            )
+            .password()
+            .on_submit(Message::CreateKey)
+            .width(Length::Units(200))
+            .padding(10)
+            .size(16),
+        )
+        .push(
+            Button::new(create, Text::new("create key").size(16))
+                .style(style::Button::Refresh)
+                .padding(10)
+                .on_press(Message::CreateKey),
+        )
+        .into()
 }
 
-impl Filter {
-    fn matches(&self, doc: &Document) -> bool {
-        match self {
-            Filter::All => true,
-            Filter::Normalized => utils::is_normalized(&doc.path),
-            Filter::Unnormalized => !utils::is_normalized(&doc.path),
-        }
+/// The errors that haven't been dismissed yet, the most recent first.
+fn status_area<'a>(status: &'a [String], dismiss: &'a mut button::State) -> Element<'a, Message> {
+    if status.is_empty() {
+        return Column::new().into();
     }
+    let errors = status
+        .iter()
+        .rev()
+        .take(STATUS_LINES)
+        .fold(Column::new().spacing(5), |column, error| {
+            column.push(Text::new(error.as_str()).size(14))
+        });
+    Container::new(
+        Row::new()
+            .spacing(10)
+            .align_items(Align::Center)
+            .push(errors.width(Length::Fill))
+            .push(
+                Button::new(dismiss, Text::new("X").size(10))
+                    .padding(10)
+                    .style(style::Button::Destructive)
+                    .on_press(Message::DismissStatus),
+            ),
+    )
+    .width(Length::Fill)
+    .padding(10)
+    .style(style::Status {})
+    .into()
 }
 
 fn loading_message<'a>() -> Element<'a, Message> {
@@ -815,7 +2531,7 @@ const ICONS: Font = Font::External {
 };
 
 fn icon(unicode: char) -> Text {
-    Text::new(&unicode.to_string())
+    Text::new(unicode.to_string())
         .font(ICONS)
         .width(Length::Units(20))
         .horizontal_alignment(HorizontalAlignment::Center)
@@ -830,10 +2546,32 @@ fn delete_icon() -> Text {
     icon('\u{F1F8}')
 }
 
+// The icon font has no lock glyph.
+const LOCK_ICON: &[u8] = include_bytes!("../icons/lock.png");
+
+fn lock_icon() -> Image {
+    Image::new(image::Handle::from_memory(LOCK_ICON.to_vec()))
+        .width(Length::Units(20))
+        .height(Length::Units(20))
+}
+
+/// Records an event in the activity log. Failing to write it is only worth a mention on stderr,
+/// since reporting it would need the log too.
+fn log(event: ActivityEvent) {
+    if let Err(e) = SavedState::activity_log().append(&event) {
+        eprintln!("error: could not write the activity log: {}: {}", e, event);
+    }
+}
+
 // Persistence
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SavedState {
     target_dir: String,
+    /// How the documents are listed. States saved before it existed list them by date.
+    #[serde(default)]
+    sort: SortOrder,
+    #[serde(default)]
+    group_by: GroupBy,
 }
 
 #[derive(Debug, Clone)]
@@ -843,6 +2581,7 @@ enum LoadError {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 enum SaveError {
     DirectoryError,
     FileError,
@@ -858,7 +2597,7 @@ impl SavedState {
         {
             project_dirs.data_dir().into()
         } else {
-            std::env::current_dir().unwrap_or(std::path::PathBuf::new())
+            std::env::current_dir().unwrap_or_default()
         };
 
         path.push("filecabinet.json");
@@ -866,6 +2605,11 @@ impl SavedState {
         path
     }
 
+    /// The activity log, next to the saved state.
+    fn activity_log() -> ActivityLog {
+        ActivityLog::new(Self::path().with_file_name(activity::LOG_FILE))
+    }
+
     async fn load() -> Result<SavedState, LoadError> {
         let mut contents = String::new();
 
@@ -916,6 +2660,11 @@ impl SavedState {
         window.local_storage().ok()?
     }
 
+    /// There are no files to keep the activity log in, so events can't be written or read.
+    fn activity_log() -> ActivityLog {
+        ActivityLog::new(activity::LOG_FILE)
+    }
+
     async fn load() -> Result<SavedState, LoadError> {
         let storage = Self::storage().ok_or(LoadError::FileError)?;
 
@@ -948,6 +2697,29 @@ mod style {
 
     pub struct Pane {}
 
+    /// The status area, where errors are shown.
+    pub struct Status {}
+
+    impl container::StyleSheet for Status {
+        fn style(&self) -> container::Style {
+            container::Style {
+                text_color: Some(Color::from_rgb(0.6, 0.1, 0.2)),
+                background: Some(Background::Color(Color::from_rgb(
+                    0xfd as f32 / 255.0,
+                    0xe2 as f32 / 255.0,
+                    0xe7 as f32 / 255.0,
+                ))),
+                border_width: 1.0,
+                border_radius: 5.0,
+                border_color: Color::from_rgb(
+                    0xef as f32 / 255.0,
+                    0x47 as f32 / 255.0,
+                    0x6f as f32 / 255.0,
+                ),
+            }
+        }
+    }
+
     impl container::StyleSheet for Pane {
         fn style(&self) -> container::Style {
             container::Style {
@@ -0,0 +1,160 @@
+use crate::atomic;
+use crate::config::CONFIG_DIR;
+use crate::errors::Result;
+use crate::utils;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// What a document's file looked like when it was recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub sha256: String,
+    pub size: u64,
+    /// Seconds since the Unix epoch.
+    pub mtime: u64,
+}
+
+impl Entry {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Entry> {
+        let data = fs::read(&path)?;
+        let mtime = fs::metadata(&path)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Ok(Entry {
+            sha256: atomic::checksum(&data),
+            size: data.len() as u64,
+            mtime,
+        })
+    }
+}
+
+/// Digests of a cabinet's documents, stored in `.filecabinet/manifest.json` and keyed by
+/// their path relative to the cabinet, as `utils::path_to_string` gives it, to detect files
+/// that changed on disk.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: BTreeMap<String, Entry>,
+}
+
+/// Differences between a manifest and the documents on disk.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// Recorded documents that no longer exist.
+    pub missing: Vec<String>,
+    /// Recorded documents whose contents changed.
+    pub modified: Vec<String>,
+    /// Documents that aren't recorded.
+    pub unknown: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty() && self.unknown.is_empty()
+    }
+}
+
+impl Manifest {
+    pub fn path<P: AsRef<Path>>(root: P) -> PathBuf {
+        root.as_ref().join(CONFIG_DIR).join(MANIFEST_FILE)
+    }
+
+    /// Loads the manifest of the cabinet at `root`, or an empty one if it has none.
+    pub fn load<P: AsRef<Path>>(root: P) -> Result<Manifest> {
+        let path = Self::path(root);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, root: P) -> Result<()> {
+        let path = Self::path(root);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        atomic::replace(path, serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Records the current state of the document at `name`, relative to `root`.
+    pub fn record<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, root: P, name: Q) -> io::Result<()> {
+        let entry = Entry::new(root.as_ref().join(&name))?;
+        self.entries.insert(utils::path_to_string(name), entry);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.entries.remove(name);
+    }
+
+    /// Moves the entry of a renamed document, whose contents haven't changed.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(entry) = self.entries.remove(old) {
+            self.entries.insert(new.to_string(), entry);
+        }
+    }
+
+    /// Compares the manifest with the documents named `names`, relative to `root`, hashing
+    /// every recorded file in parallel.
+    pub fn verify<P: AsRef<Path> + Sync>(&self, root: P, names: &[String]) -> Report {
+        let mut report = Report::default();
+        let changes: Vec<(&String, Option<bool>)> = self
+            .entries
+            .par_iter()
+            .map(|(name, entry)| {
+                let path = root.as_ref().join(utils::path_from_string(name));
+                match Entry::new(path) {
+                    Ok(current) => (name, Some(current.sha256 != entry.sha256)),
+                    Err(_) => (name, None),
+                }
+            })
+            .collect();
+        for (name, modified) in changes {
+            match modified {
+                None => report.missing.push(name.clone()),
+                Some(true) => report.modified.push(name.clone()),
+                Some(false) => {}
+            }
+        }
+        let recorded: BTreeSet<&String> = self.entries.keys().collect();
+        report.unknown = names
+            .iter()
+            .filter(|name| !recorded.contains(name))
+            .cloned()
+            .collect();
+        report
+    }
+}
+
+#[test]
+fn test_verify() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    let names: Vec<String> = ["a.pdf", "b.pdf", "c.pdf"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    let mut manifest = Manifest::default();
+    for name in &names[..2] {
+        fs::write(root.join(name), name).unwrap();
+        manifest.record(root, name).unwrap();
+    }
+    fs::write(root.join("c.pdf"), "c").unwrap();
+    assert_eq!(manifest.verify(root, &names).unknown, vec!["c.pdf"]);
+
+    fs::write(root.join("a.pdf"), "rot").unwrap();
+    fs::remove_file(root.join("b.pdf")).unwrap();
+    let report = manifest.verify(root, &names[..1]);
+    assert_eq!(report.modified, vec!["a.pdf"]);
+    assert_eq!(report.missing, vec!["b.pdf"]);
+    assert!(report.unknown.is_empty());
+}
@@ -0,0 +1,117 @@
+use crate::filter::Matcher;
+use crate::Document;
+
+/// A search for documents, such as `chase year:2021 ext:pdf`. Every term has to match: plain
+/// words match the date, institution, title, filename or extension, while `field:value` terms
+/// only match that field. Double quotes keep spaces in a term, e.g. `title:"tax return"`.
+/// Matching ignores case.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Any(String),
+    Field(String, String),
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Query {
+        let terms = tokens(text)
+            .into_iter()
+            .map(|token| {
+                let token = token.to_lowercase();
+                match token.split_once(':') {
+                    Some((field, value)) if !field.is_empty() && !value.is_empty() => {
+                        Term::Field(field.to_string(), value.to_string())
+                    }
+                    _ => Term::Any(token),
+                }
+            })
+            .collect();
+        Query { terms }
+    }
+
+    /// Whether the query has no terms, so that every document matches.
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+}
+
+impl Matcher for Query {
+    fn matches(&self, doc: &Document) -> bool {
+        self.terms.iter().all(|term| term.matches(doc))
+    }
+}
+
+impl Term {
+    fn matches(&self, doc: &Document) -> bool {
+        let contains = |field: &str, value: &str| field.to_lowercase().contains(value);
+        match self {
+            Term::Any(value) => [
+                &doc.date,
+                &doc.institution,
+                &doc.title,
+                &doc.filename,
+                &doc.extension,
+            ]
+            .iter()
+            .any(|field| contains(field, value)),
+            Term::Field(field, value) => match field.as_str() {
+                "year" => doc.year() == Some(value.as_str()),
+                // Months can be given without their leading zero.
+                "month" => doc.month().is_some_and(|month| {
+                    month.trim_start_matches('0') == value.trim_start_matches('0')
+                }),
+                "date" => doc.date.starts_with(value.as_str()),
+                "ext" | "extension" => {
+                    doc.extension.to_lowercase() == value.trim_start_matches('.')
+                }
+                "name" | "filename" => contains(&doc.filename, value),
+                "folder" => contains(&doc.folder, value),
+                field => doc.field(field).is_some_and(|field| contains(field, value)),
+            },
+        }
+    }
+}
+
+/// Splits `text` on whitespace outside of double quotes, dropping the quotes.
+fn tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+#[test]
+fn test_query() {
+    let template = crate::Template::default();
+    let doc = Document::new("/tmp/2021-03-01_Chase_Tax Return_1.pdf", &template).unwrap();
+    assert!(Query::parse("").matches(&doc));
+    assert!(Query::parse("chase").matches(&doc));
+    assert!(Query::parse("institution:chase year:2021 ext:pdf").matches(&doc));
+    assert!(Query::parse("month:3 date:2021-03 ext:.PDF").matches(&doc));
+    assert!(Query::parse("title:\"tax return\"").matches(&doc));
+    assert!(!Query::parse("title:\"return tax\"").matches(&doc));
+    assert!(!Query::parse("chase year:2020").matches(&doc));
+    assert!(!Query::parse("owner:alex").matches(&doc));
+    assert_eq!(
+        tokens(r#"a  b:"c d" e"#),
+        vec!["a".to_string(), "b:c d".to_string(), "e".to_string()]
+    );
+}
@@ -0,0 +1,60 @@
+//! Destruction of a file's contents before it's deleted.
+//!
+//! The file is overwritten in place with random data, synced to disk after each pass, and only
+//! then unlinked. Copy-on-write filesystems and SSDs may still keep the old blocks around, so
+//! this is a precaution rather than a guarantee.
+use rand::RngCore;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Number of passes when the cabinet's config doesn't set `shred_passes`.
+pub const DEFAULT_PASSES: u32 = 3;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Overwrites the file at `path` with random data `passes` times, then removes it.
+pub fn shred_file<P: AsRef<Path>>(path: P, passes: u32) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    overwrite(&mut file, passes)?;
+    drop(file);
+    fs::remove_file(path)
+}
+
+fn overwrite(file: &mut File, passes: u32) -> io::Result<()> {
+    let len = file.metadata()?.len();
+    let mut rng = rand::thread_rng();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    for _ in 0..passes {
+        file.seek(SeekFrom::Start(0))?;
+        let mut left = len;
+        while left > 0 {
+            let chunk = &mut buffer[..left.min(BUFFER_SIZE as u64) as usize];
+            rng.fill_bytes(chunk);
+            file.write_all(chunk)?;
+            left -= chunk.len() as u64;
+        }
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_shred() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let path = dir.join("2020-01-01_Tax_Return_1.pdf");
+    let contents = vec![b'x'; BUFFER_SIZE + 10];
+    fs::write(&path, &contents).unwrap();
+
+    let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+    overwrite(&mut file, 2).unwrap();
+    let overwritten = fs::read(&path).unwrap();
+    assert_eq!(overwritten.len(), contents.len());
+    assert_ne!(overwritten, contents);
+
+    shred_file(&path, 1).unwrap();
+    assert!(!path.exists());
+    assert!(shred_file(&path, 1).is_err());
+}
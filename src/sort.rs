@@ -0,0 +1,141 @@
+use crate::Document;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::time::SystemTime;
+
+/// What documents can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Date,
+    Institution,
+    Title,
+    Page,
+    /// The file's size in bytes.
+    Size,
+    /// When the file was last modified.
+    Modified,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 6] = [
+        SortKey::Date,
+        SortKey::Institution,
+        SortKey::Title,
+        SortKey::Page,
+        SortKey::Size,
+        SortKey::Modified,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Date => "date",
+            SortKey::Institution => "institution",
+            SortKey::Title => "title",
+            SortKey::Page => "page",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+        }
+    }
+
+    fn value(self, doc: &Document) -> Value {
+        match self {
+            SortKey::Date => Value::Text(doc.date.clone()),
+            SortKey::Institution => Value::Text(doc.institution.to_lowercase()),
+            SortKey::Title => Value::Text(doc.title.to_lowercase()),
+            SortKey::Page => Value::Number(page(doc).map(u64::from)),
+            SortKey::Size => Value::Number(fs::metadata(&doc.path).ok().map(|m| m.len())),
+            SortKey::Modified => {
+                Value::Time(fs::metadata(&doc.path).and_then(|m| m.modified()).ok())
+            }
+        }
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A document's value for a `SortKey`. Files that can't be read come first.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Value {
+    Text(String),
+    Number(Option<u64>),
+    Time(Option<SystemTime>),
+}
+
+/// The page number of a document, if it has one.
+fn page(doc: &Document) -> Option<u32> {
+    doc.page.parse().ok()
+}
+
+/// How documents are listed: by a key, in ascending or descending order. Documents with the
+/// same value are sorted by page in ascending order either way, so that the pages of a document
+/// stay in order, and then by filename.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl SortOrder {
+    /// Sorts `items` by the document `doc` gives for each. Files are read at most once for
+    /// their size or modification time.
+    pub fn sort<T, F>(self, items: &mut Vec<T>, doc: F)
+    where
+        F: Fn(&T) -> &Document,
+    {
+        let mut keyed: Vec<(Value, T)> = items
+            .drain(..)
+            .map(|item| (self.key.value(doc(&item)), item))
+            .collect();
+        keyed.sort_by(|(a_value, a), (b_value, b)| {
+            let (a, b) = (doc(a), doc(b));
+            let order = a_value.cmp(b_value);
+            let order = if self.descending {
+                order.reverse()
+            } else {
+                order
+            };
+            order
+                .then_with(|| page(a).cmp(&page(b)))
+                .then_with(|| a.filename.cmp(&b.filename))
+        });
+        items.extend(keyed.into_iter().map(|(_, item)| item));
+    }
+}
+
+#[test]
+fn test_sort() {
+    let template = crate::Template::default();
+    let mut docs: Vec<Document> = [
+        "/tmp/2021-01-01_Chase_Statement_2.pdf",
+        "/tmp/2020-06-01_amex_Statement_1.pdf",
+        "/tmp/2021-01-01_Chase_Statement_10.pdf",
+        "/tmp/2021-01-01_Chase_Statement_1.pdf",
+    ]
+    .iter()
+    .map(|path| Document::new(*path, &template).unwrap())
+    .collect();
+    let names = |docs: &[Document]| -> Vec<String> {
+        docs.iter()
+            .map(|doc| format!("{}/{}", doc.institution, doc.page))
+            .collect()
+    };
+    let mut order = SortOrder::default();
+    order.sort(&mut docs, |doc| doc);
+    assert_eq!(names(&docs), ["amex/1", "Chase/1", "Chase/2", "Chase/10"]);
+    order.descending = true;
+    order.sort(&mut docs, |doc| doc);
+    assert_eq!(names(&docs), ["Chase/1", "Chase/2", "Chase/10", "amex/1"]);
+    order.key = SortKey::Institution;
+    order.sort(&mut docs, |doc| doc);
+    assert_eq!(names(&docs), ["Chase/1", "Chase/2", "Chase/10", "amex/1"]);
+    let json = serde_json::to_string(&order).unwrap();
+    assert_eq!(json, r#"{"key":"institution","descending":true}"#);
+}
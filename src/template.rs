@@ -0,0 +1,255 @@
+use crate::errors::{Error, ErrorKind, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The naming scheme used when a cabinet doesn't configure one.
+pub const DEFAULT_TEMPLATE: &str = "{date}_{institution}_{title}_{page}";
+
+/// Fields every document has. Templates may also refer to custom fields declared in the
+/// cabinet's config.
+pub const FIELDS: [&str; 4] = ["date", "institution", "title", "page"];
+
+lazy_static! {
+    static ref RE_PLACEHOLDER: Regex = Regex::new(r"\{(?P<name>\w+)(?P<optional>\?)?\}").unwrap();
+    static ref RE_ISO_DATE: Regex = Regex::new(r"^\d{4}-\d{2}-\d{2}").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub optional: bool,
+}
+
+/// A filename scheme such as `{institution}-{date}-{title}-{page?}`: fields joined by a single
+/// separator, where fields marked with `?` may be left out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    separator: String,
+    fields: Vec<Field>,
+}
+
+impl Template {
+    pub fn parse(spec: &str) -> Result<Template> {
+        let invalid = |reason: &str| -> Error {
+            ErrorKind::InvalidTemplate(spec.to_string(), reason.to_string()).into()
+        };
+        let mut fields: Vec<Field> = Vec::new();
+        let mut separator: Option<&str> = None;
+        let mut last_end = 0;
+        for captures in RE_PLACEHOLDER.captures_iter(spec) {
+            let placeholder = captures.get(0).unwrap();
+            let literal = &spec[last_end..placeholder.start()];
+            if fields.is_empty() {
+                if !literal.is_empty() {
+                    return Err(invalid("text before the first field"));
+                }
+            } else if literal.is_empty() {
+                return Err(invalid("fields must be separated"));
+            } else if *separator.get_or_insert(literal) != literal {
+                return Err(invalid("fields must all use the same separator"));
+            }
+            let name = captures["name"].to_string();
+            if fields.iter().any(|f| f.name == name) {
+                return Err(invalid(&format!("field \"{}\" is used twice", name)));
+            }
+            fields.push(Field {
+                name,
+                optional: captures.name("optional").is_some(),
+            });
+            last_end = placeholder.end();
+        }
+        if fields.is_empty() {
+            return Err(invalid("no fields"));
+        }
+        if last_end != spec.len() {
+            return Err(invalid("text after the last field"));
+        }
+        Ok(Template {
+            separator: separator.unwrap_or("_").to_string(),
+            fields,
+        })
+    }
+
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn has_field(&self, name: &str) -> bool {
+        self.fields.iter().any(|f| f.name == name)
+    }
+
+    /// The fields of the template that aren't one of the built-in `FIELDS`.
+    pub fn custom_fields(&self) -> impl Iterator<Item = &Field> {
+        self.fields
+            .iter()
+            .filter(|f| !FIELDS.contains(&f.name.as_str()))
+    }
+
+    pub fn is_required(&self, name: &str) -> bool {
+        self.fields.iter().any(|f| f.name == name && !f.optional)
+    }
+
+    /// Splits a filename stem on the separator. ISO dates are kept whole even if the
+    /// separator is a hyphen.
+    pub fn split<'a>(&self, stem: &'a str) -> Vec<&'a str> {
+        let sep = self.separator.as_str();
+        let mut parts = Vec::new();
+        let mut rest = stem;
+        loop {
+            let date_end = RE_ISO_DATE
+                .find(rest)
+                .map(|m| m.end())
+                .filter(|&end| rest[..end].contains(sep))
+                .filter(|&end| rest[end..].is_empty() || rest[end..].starts_with(sep));
+            match date_end.or_else(|| rest.find(sep)) {
+                Some(end) if end < rest.len() => {
+                    parts.push(&rest[..end]);
+                    rest = &rest[end + sep.len()..];
+                }
+                _ => {
+                    parts.push(rest);
+                    return parts;
+                }
+            }
+        }
+    }
+
+    /// Assigns the parts of a filename stem to fields. Optional fields are filled in order
+    /// only when there are more parts than required fields. Missing fields are `None`.
+    pub fn assign<'a>(&self, stem: &'a str) -> Vec<(&str, Option<&'a str>)> {
+        let parts = self.split(stem);
+        let required = self.fields.iter().filter(|f| !f.optional).count();
+        let mut optional_slots = parts.len().saturating_sub(required);
+        let mut parts = parts.into_iter();
+        self.fields
+            .iter()
+            .map(|field| {
+                let value = if !field.optional {
+                    parts.next()
+                } else if optional_slots > 0 {
+                    optional_slots -= 1;
+                    parts.next()
+                } else {
+                    None
+                };
+                (field.name.as_str(), value)
+            })
+            .collect()
+    }
+
+    /// Joins field values into a filename stem. Empty optional fields are left out.
+    pub fn render<'a, F>(&self, value: F) -> String
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        self.fields
+            .iter()
+            .filter_map(|field| match value(&field.name) {
+                Some(v) if !v.is_empty() => Some(v),
+                _ if field.optional => None,
+                v => Some(v.unwrap_or("")),
+            })
+            .collect::<Vec<&str>>()
+            .join(&self.separator)
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template::parse(DEFAULT_TEMPLATE).unwrap()
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| {
+                format!(
+                    "{{{}{}}}",
+                    field.name,
+                    if field.optional { "?" } else { "" }
+                )
+            })
+            .collect();
+        write!(f, "{}", fields.join(&self.separator))
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = Error;
+
+    fn try_from(spec: String) -> Result<Template> {
+        Template::parse(&spec)
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> String {
+        template.to_string()
+    }
+}
+
+#[test]
+fn test_parse_template() {
+    let template = Template::parse("{institution}-{date}-{title}-{page?}").unwrap();
+    assert_eq!(template.separator(), "-");
+    assert_eq!(template.fields().len(), 4);
+    assert!(template.is_required("date"));
+    assert!(!template.is_required("page"));
+    assert_eq!(template.to_string(), "{institution}-{date}-{title}-{page?}");
+    assert!(Template::parse("{date}_{institution}-{title}").is_err());
+    assert!(Template::parse("{date}{title}").is_err());
+    assert!(Template::parse("scan_{date}").is_err());
+}
+
+#[test]
+fn test_split_keeps_dates_whole() {
+    let template = Template::parse("{institution}-{date}-{title}").unwrap();
+    assert_eq!(
+        template.split("Bank-2020-01-01-Statement"),
+        vec!["Bank", "2020-01-01", "Statement"]
+    );
+    assert_eq!(
+        Template::default().split("2020-01-01_Bank_Statement_1"),
+        vec!["2020-01-01", "Bank", "Statement", "1"]
+    );
+}
+
+#[test]
+fn test_assign_optional_fields() {
+    let template = Template::parse("{institution}-{page?}-{title}").unwrap();
+    assert_eq!(
+        template.assign("Bank-Statement"),
+        vec![
+            ("institution", Some("Bank")),
+            ("page", None),
+            ("title", Some("Statement"))
+        ]
+    );
+    assert_eq!(
+        template.assign("Bank-2-Statement"),
+        vec![
+            ("institution", Some("Bank")),
+            ("page", Some("2")),
+            ("title", Some("Statement"))
+        ]
+    );
+    let render = |page: &'static str| {
+        template.render(|name| match name {
+            "institution" => Some("Bank"),
+            "page" => Some(page),
+            _ => Some("Statement"),
+        })
+    };
+    assert_eq!(render(""), "Bank-Statement");
+    assert_eq!(render("2"), "Bank-2-Statement");
+}
@@ -0,0 +1,159 @@
+use crate::atomic;
+use crate::utils;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory inside the cabinet that deleted documents are moved to. It's hidden, so it isn't
+/// scanned.
+pub const TRASH_DIR: &str = ".trash";
+const INDEX_FILE: &str = "index.json";
+
+/// A deleted document waiting in the trash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Name of the file in the trash directory. Like `path`, it's stored as
+    /// `utils::path_to_string` gives it.
+    pub name: String,
+    /// Where the document was, relative to the cabinet.
+    pub path: String,
+    /// When it was deleted, in RFC 3339.
+    pub deleted: String,
+}
+
+impl Entry {
+    /// The entry's path relative to the cabinet.
+    pub fn trash_path(&self) -> String {
+        Trash::path_of(&self.name)
+    }
+
+    /// Whether the entry was deleted before `time`. Entries with an unreadable date never are.
+    pub fn is_older(&self, time: DateTime<Utc>) -> bool {
+        DateTime::parse_from_rfc3339(&self.deleted).is_ok_and(|deleted| deleted < time)
+    }
+}
+
+/// The index of a cabinet's trash, `.trash/index.json`, which remembers where each deleted
+/// document came from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Trash {
+    /// Entries in the order they were deleted.
+    pub entries: Vec<Entry>,
+}
+
+impl Trash {
+    pub fn dir<P: AsRef<Path>>(root: P) -> PathBuf {
+        root.as_ref().join(TRASH_DIR)
+    }
+
+    /// The path relative to the cabinet of the file named `name` in the trash.
+    pub fn path_of(name: &str) -> String {
+        utils::path_to_string(Path::new(TRASH_DIR).join(utils::path_from_string(name)))
+    }
+
+    /// The name in the trash of the file at `path`, relative to the cabinet, if it's in it.
+    pub fn name_of(path: &str) -> Option<String> {
+        let path = utils::path_from_string(path);
+        let name = path.strip_prefix(TRASH_DIR).ok()?;
+        Some(utils::path_to_string(name)).filter(|name| !name.is_empty())
+    }
+
+    /// Loads the trash index of the cabinet at `root`, leaving out entries whose file is gone.
+    pub fn load<P: AsRef<Path>>(root: P) -> io::Result<Trash> {
+        let dir = Self::dir(root);
+        let path = dir.join(INDEX_FILE);
+        if !path.exists() {
+            return Ok(Trash::default());
+        }
+        let mut trash: Trash = serde_json::from_str(&fs::read_to_string(path)?)?;
+        trash
+            .entries
+            .retain(|entry| dir.join(utils::path_from_string(&entry.name)).exists());
+        Ok(trash)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, root: P) -> io::Result<()> {
+        let dir = Self::dir(root);
+        fs::create_dir_all(&dir)?;
+        atomic::replace(
+            dir.join(INDEX_FILE),
+            serde_json::to_string_pretty(self)?.as_bytes(),
+        )
+    }
+
+    /// A name in the trash for the document at `path` that no other file has.
+    pub fn free_name<P: AsRef<Path>>(root: P, path: &str) -> String {
+        let dir = Self::dir(root);
+        let path = utils::path_from_string(path);
+        let file_name = path.file_name().unwrap_or(path.as_os_str());
+        let name = (0..)
+            .map(|i| {
+                let mut name = file_name.to_os_string();
+                if i > 0 {
+                    name.push(format!(".{}", i));
+                }
+                name
+            })
+            .find(|name| name != INDEX_FILE && !dir.join(name).exists())
+            .unwrap();
+        utils::path_to_string(name)
+    }
+
+    /// Records that the document at `path` was moved to the trash as `name`.
+    pub fn add(&mut self, name: &str, path: &str) {
+        self.remove(name);
+        self.entries.push(Entry {
+            name: name.to_string(),
+            path: path.to_string(),
+            deleted: Utc::now().to_rfc3339(),
+        });
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Entry> {
+        let i = self.entries.iter().position(|entry| entry.name == name)?;
+        Some(self.entries.remove(i))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// The entries deleted more than `days` ago.
+    pub fn expired(&self, days: u32) -> Vec<Entry> {
+        let limit = Utc::now() - Duration::days(days.into());
+        self.entries
+            .iter()
+            .filter(|entry| entry.is_older(limit))
+            .cloned()
+            .collect()
+    }
+}
+
+#[test]
+fn test_trash_index() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path();
+    fs::create_dir_all(Trash::dir(root)).unwrap();
+    fs::write(Trash::dir(root).join("a.pdf"), "a").unwrap();
+    assert_eq!(Trash::free_name(root, "2021/a.pdf"), "a.pdf.1");
+    assert_eq!(Trash::free_name(root, "b.pdf"), "b.pdf");
+
+    let mut trash = Trash::default();
+    trash.add("a.pdf", "2021/a.pdf");
+    trash.add("b.pdf", "b.pdf");
+    trash.entries[0].deleted = "2020-01-01T00:00:00+00:00".to_string();
+    assert_eq!(trash.expired(30).len(), 1);
+    assert_eq!(
+        Trash::name_of(&trash.entries[0].trash_path()),
+        Some("a.pdf".to_string())
+    );
+    assert_eq!(Trash::name_of("2021/a.pdf"), None);
+    trash.save(root).unwrap();
+    // b.pdf was never moved to the trash.
+    let trash = Trash::load(root).unwrap();
+    assert_eq!(trash.entries.len(), 1);
+    assert_eq!(trash.get("a.pdf").unwrap().path, "2021/a.pdf");
+}
@@ -1,59 +1,101 @@
+use crate::crypto::{self, ENCRYPTED_EXTENSION};
+use crate::errors::{ErrorKind, Result};
+use crate::template::Template;
 use crate::Document;
+use data_encoding::HEXLOWER;
 use regex::Regex;
+use std::collections::BTreeMap;
 
 use std::ffi::OsStr;
+use std::io;
 use std::path::{Path, PathBuf};
 
 pub struct OptDoc {
-    pub(crate) date: Option<String>,
-    pub(crate) institution: Option<String>,
-    pub(crate) name: Option<String>,
-    pub(crate) page: Option<String>,
+    pub date: Option<String>,
+    pub institution: Option<String>,
+    pub name: Option<String>,
+    pub page: Option<String>,
+    pub fields: BTreeMap<String, String>,
 }
 
 /// Represents a Document with fields that were maybe parseable
 impl OptDoc {
-    pub fn new<T: AsRef<Path>>(filename: T) -> OptDoc {
-        let filename = filename.as_ref();
-        let filestem: &str = filename
+    pub fn new<T: AsRef<Path>>(filename: T, template: &Template) -> OptDoc {
+        let filename = crypto::decrypted_path(filename);
+        let filename = filename.as_path();
+        let filestem = filename
             .file_stem()
-            .and_then(OsStr::to_str)
-            .unwrap_or(filename.to_str().unwrap());
-        let v: Vec<&str> = filestem.split('_').collect();
-        OptDoc {
-            date: v.get(0).and_then(parse_date),
-            institution: v.get(1).map(|x| x.to_string()),
-            name: v.get(2).map(|x| x.to_string()),
-            page: v.get(3).and_then(parse_page),
+            .unwrap_or(filename.as_os_str())
+            .to_string_lossy();
+        let mut doc = OptDoc {
+            date: None,
+            institution: None,
+            name: None,
+            page: None,
+            fields: BTreeMap::new(),
+        };
+        for (field, value) in template.assign(&filestem) {
+            match field {
+                "date" => doc.date = value.as_ref().and_then(parse_date),
+                "institution" => doc.institution = value.map(str::to_string),
+                "title" => doc.name = value.map(str::to_string),
+                "page" => doc.page = value.as_ref().and_then(parse_page),
+                custom => {
+                    if let Some(value) = value {
+                        doc.fields.insert(custom.to_string(), value.to_string());
+                    }
+                }
+            }
         }
+        doc
     }
-    pub fn is_parseable(&self) -> bool {
-        self.date.is_some()
-            && self.institution.is_some()
-            && self.name.is_some()
-            && self.page.is_some()
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        match name {
+            "date" => self.date.as_deref(),
+            "institution" => self.institution.as_deref(),
+            "title" => self.name.as_deref(),
+            "page" => self.page.as_deref(),
+            custom => self.fields.get(custom).map(String::as_str),
+        }
+    }
+
+    /// The required fields of `template` that couldn't be parsed.
+    pub fn missing<'a>(&self, template: &'a Template) -> Vec<&'a str> {
+        template
+            .fields()
+            .iter()
+            .filter(|f| !f.optional && self.field(&f.name).is_none())
+            .map(|f| f.name.as_str())
+            .collect()
+    }
+
+    pub fn is_parseable(&self, template: &Template) -> bool {
+        self.missing(template).is_empty()
     }
 }
 
-pub fn is_normalized<P: AsRef<Path>>(source: P) -> bool {
+pub fn is_normalized<P: AsRef<Path>>(source: P, template: &Template) -> bool {
     let source = source.as_ref();
+    if crypto::is_encrypted(source) {
+        // The encrypted extension is appended to the normalized name as is.
+        return source.extension() == Some(OsStr::new(ENCRYPTED_EXTENSION))
+            && is_normalized(crypto::decrypted_path(source), template);
+    }
     let extension: String = source
         .extension()
         .and_then(std::ffi::OsStr::to_str)
         .map(|s| s.to_ascii_lowercase())
-        .unwrap_or(String::new());
-    let doc = OptDoc::new(source);
-    if !doc.is_parseable() {
+        .unwrap_or_default();
+    let doc = OptDoc::new(source, template);
+    if !doc.is_parseable(template) {
         return false;
     }
     match source.parent() {
         Some(basename) => {
             let target = basename.join(format!(
-                "{}_{}_{}_{}.{}",
-                doc.date.expect("date error"),
-                doc.institution.expect("institution error"),
-                doc.name.expect("name error"),
-                doc.page.unwrap_or("1".to_owned()),
+                "{}.{}",
+                template.render(|name| doc.field(name)),
                 extension
             ));
             source == target.as_path()
@@ -62,46 +104,151 @@ pub fn is_normalized<P: AsRef<Path>>(source: P) -> bool {
     }
 }
 
-pub fn read_docs(path: &str) -> Vec<Document> {
-    let dir_path = Path::new(&path).to_path_buf();
-    list_files(&dir_path)
+/// Reads the documents in the directory at `path`, and in its subfolders if `recursive`.
+pub fn read_docs(path: &Path, template: &Template, recursive: bool) -> Result<Vec<Document>> {
+    list_files(path, recursive)?
         .iter()
-        .map(|path| {
-            let mut full_path = dir_path.clone();
-            full_path.push(path);
-            Document::new(full_path.to_str().unwrap().to_string())
+        .map(|name| {
+            let mut doc = Document::new(path.join(name), template)?;
+            if let Some(folder) = name.parent() {
+                doc.folder = folder
+                    .iter()
+                    .map(|part| part.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+            }
+            Ok(doc)
         })
         .collect()
 }
 
+/// Marks a path stored as the hex of its bytes.
+const BYTES_PREFIX: &str = "bytes:";
+
+/// The path as the manifest, the journal, the trash index and the activity log store it. Paths
+/// that aren't valid UTF-8, or that start like an encoded one, are stored as the hex of their
+/// bytes so that `path_from_string` gives the same path back.
+pub fn path_to_string<P: AsRef<Path>>(path: P) -> String {
+    let path = path.as_ref();
+    match path.to_str() {
+        Some(s) if !s.starts_with(BYTES_PREFIX) => s.to_string(),
+        _ => encode_bytes(path),
+    }
+}
+
+#[cfg(unix)]
+fn encode_bytes(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    format!(
+        "{}{}",
+        BYTES_PREFIX,
+        HEXLOWER.encode(path.as_os_str().as_bytes())
+    )
+}
+
+/// Names that aren't valid Unicode are rare elsewhere, so they're stored with their invalid
+/// characters replaced.
+#[cfg(not(unix))]
+fn encode_bytes(path: &Path) -> String {
+    match path.to_str() {
+        Some(s) => format!("{}{}", BYTES_PREFIX, HEXLOWER.encode(s.as_bytes())),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// The path stored as `s` by `path_to_string`.
+pub fn path_from_string(s: &str) -> PathBuf {
+    let bytes = s
+        .strip_prefix(BYTES_PREFIX)
+        .and_then(|hex| HEXLOWER.decode(hex.as_bytes()).ok());
+    match bytes {
+        Some(bytes) => path_from_bytes(bytes),
+        None => PathBuf::from(s),
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
 pub fn extension<P: AsRef<Path>>(source: P) -> String {
     source
         .as_ref()
         .extension()
         .and_then(std::ffi::OsStr::to_str)
         .map(|s| s.to_ascii_lowercase())
-        .unwrap_or(String::new())
+        .unwrap_or_default()
 }
 
 // TODO: use async paths
-pub fn list_files(path: &PathBuf) -> Vec<String> {
+/// Lists the documents in the directory at `path` by their path relative to it. Hidden folders,
+/// like the cabinet's config, aren't scanned, and neither are symlinked folders, which could
+/// lead back up the tree. Fails if a folder can't be read.
+pub fn list_files(path: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
     if !path.exists() {
-        return Vec::new();
+        return Ok(Vec::new());
     }
-    path.read_dir()
-        .expect("read_dir call failed")
-        .map(|x| x.unwrap().path())
-        .filter(|x| Path::new(x).is_file())
+    let entries = read_entries(path)?;
+    let mut files: Vec<PathBuf> = Vec::new();
+    if recursive {
+        for dir in entries.iter().filter(|x| is_scanned_folder(x)) {
+            let name = dir.file_name().unwrap_or_default();
+            for file in list_files(dir, true)? {
+                files.push(Path::new(name).join(file));
+            }
+        }
+    }
+    let documents = entries
+        .into_iter()
+        .filter(|x| x.is_file())
         .filter(|x| {
-            let ext: String = x
-                .extension()
-                .and_then(std::ffi::OsStr::to_str)
-                .map(|s| s.to_ascii_lowercase())
-                .unwrap_or(String::new());
+            let ext = extension(x);
             ext == "pdf" || ext == "jpg" || ext == "png" || ext == "cocoon"
         })
-        .map(|x| x.file_name().unwrap().to_str().unwrap().to_owned())
-        .collect()
+        .filter_map(|x| x.file_name().map(PathBuf::from));
+    files.extend(documents);
+    Ok(files)
+}
+
+/// The folders below `path` that `list_files` scans when it's recursive.
+pub fn list_folders(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut folders = Vec::new();
+    for dir in read_entries(path)?
+        .into_iter()
+        .filter(|x| is_scanned_folder(x))
+    {
+        folders.extend(list_folders(&dir)?);
+        folders.push(dir);
+    }
+    Ok(folders)
+}
+
+fn read_entries(path: &Path) -> Result<Vec<PathBuf>> {
+    path.read_dir()
+        .and_then(|entries| {
+            entries
+                .map(|x| x.map(|x| x.path()))
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .map_err(|e| ErrorKind::Unreadable(path.display().to_string(), e.to_string()).into())
+}
+
+/// Whether `path` is a folder that isn't hidden or a symlink.
+fn is_scanned_folder(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_none_or(|name| name.to_string_lossy().starts_with('.'));
+    !hidden && path.symlink_metadata().is_ok_and(|m| m.is_dir())
 }
 
 pub fn to_camelcase(text: &str) -> String {
@@ -208,3 +355,78 @@ fn test_parse_date_year_only() {
         Some("2018-01-01".to_string())
     )
 }
+
+#[test]
+fn test_is_normalized() {
+    let default = Template::default();
+    assert!(is_normalized(
+        "/a/2020-01-01_Bank_Statement_1.pdf",
+        &default
+    ));
+    assert!(!is_normalized("/a/20200101_Bank_Statement_1.pdf", &default));
+    assert!(!is_normalized("/a/2020-01-01_Bank_Statement.pdf", &default));
+    let template = Template::parse("{institution}-{date}-{title}-{page?}").unwrap();
+    assert!(is_normalized("/a/Bank-2020-01-01-Statement.pdf", &template));
+    assert!(is_normalized(
+        "/a/Bank-2020-01-01-Statement-2.pdf",
+        &template
+    ));
+    assert!(!is_normalized(
+        "/a/2020-01-01_Bank_Statement_1.pdf",
+        &template
+    ));
+}
+
+#[test]
+fn test_path_strings() {
+    assert_eq!(path_to_string("2021/a.pdf"), "2021/a.pdf");
+    assert_eq!(path_from_string("2021/a.pdf"), Path::new("2021/a.pdf"));
+    assert_eq!(
+        path_to_string("bytes:a.pdf"),
+        "bytes:62797465733a612e706466"
+    );
+    assert_eq!(
+        path_from_string(&path_to_string("bytes:a.pdf")),
+        Path::new("bytes:a.pdf")
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let latin1 = Path::new(OsStr::from_bytes(b"M\xfcller.pdf"));
+        assert_eq!(path_to_string(latin1), "bytes:4dfc6c6c65722e706466");
+        assert_eq!(path_from_string(&path_to_string(latin1)), latin1);
+    }
+}
+
+#[test]
+fn test_list_files_errors() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("2021")).unwrap();
+    std::fs::write(dir.join("2021/a.pdf"), "a").unwrap();
+    std::fs::write(dir.join("notes.txt"), "notes").unwrap();
+    assert_eq!(
+        list_files(dir, true).unwrap(),
+        vec![Path::new("2021").join("a.pdf")]
+    );
+    match list_files(&dir.join("notes.txt"), false) {
+        Err(crate::Error(ErrorKind::Unreadable(path, _), _)) => {
+            assert!(path.ends_with("notes.txt"))
+        }
+        other => panic!("expected an unreadable folder, got {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_list_files_symlink_loop() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("2021")).unwrap();
+    std::fs::write(dir.join("2021/a.pdf"), "a").unwrap();
+    std::os::unix::fs::symlink("..", dir.join("2021/up")).unwrap();
+    assert_eq!(
+        list_files(dir, true).unwrap(),
+        vec![Path::new("2021").join("a.pdf")]
+    );
+}